@@ -0,0 +1,179 @@
+// exposure.rs
+
+// a long exposure of the bobs, like leaving a camera's shutter open
+// each frame the path the bobs moved along since the last one is added onto
+// a texture that is never cleared, so the paths build up for as long as it
+// runs without keeping any of them, unlike the traces which are capped at max_trace
+// the paths are added to what is already there, so the places the bobs pass
+// through most often end up the brightest
+
+use macroquad::prelude::*;
+use macroquad::window::miniquad::{BlendFactor, BlendState, BlendValue, Equation, PipelineParams};
+
+use crate::pendulum::DoublePendulum;
+use crate::render::{self, Camera};
+
+
+// how much of the color of a pendulum is added each frame, the paths
+// would turn white almost at once with all of it
+const EXPOSURE: f32 = 0.25;
+
+// macroquad's own shaders, only the blending is different
+const VERTEX: &str = r#"#version 100
+attribute vec3 position;
+attribute vec2 texcoord;
+attribute vec4 color0;
+
+varying lowp vec2 uv;
+varying lowp vec4 color;
+
+uniform mat4 Model;
+uniform mat4 Projection;
+
+void main() {
+    gl_Position = Projection * Model * vec4(position, 1);
+    color = color0 / 255.0;
+    uv = texcoord;
+}"#;
+
+const FRAGMENT: &str = r#"#version 100
+varying lowp vec4 color;
+varying lowp vec2 uv;
+
+uniform sampler2D Texture;
+
+void main() {
+    gl_FragColor = color * texture2D(Texture, uv);
+}"#;
+
+
+// where the inner and outer bobs of a pendulum are on the screen
+type Bobs = ((f32, f32), (f32, f32));
+
+pub struct Exposure {
+    target: RenderTarget,
+    // adds the paths onto the texture
+    additive: Material,
+    // draws the texture onto the screen, whose color has already been multiplied by its alpha
+    composite: Material,
+    // the size of the screen the texture was made for, in pixels
+    size: (f32, f32),
+    // where the bobs of each pendulum were in the last frame
+    last: Vec<Option<Bobs>>,
+}
+
+impl Exposure {
+    // an empty exposure the size of the screen
+    pub fn new() -> Exposure {
+        let blend = |source, destination| PipelineParams {
+            color_blend: Some(BlendState::new(Equation::Add, source, destination)),
+            alpha_blend: Some(BlendState::new(Equation::Add, BlendFactor::One, BlendFactor::One)),
+            ..Default::default()
+        };
+        let material = |pipeline_params| load_material(VERTEX, FRAGMENT, MaterialParams { pipeline_params, ..Default::default() })
+            .expect("the exposure shaders are macroquad's own, so they always compile");
+
+        let size = (screen_width(), screen_height());
+        let exposure = Exposure {
+            target: screen_target(size),
+            additive: material(blend(BlendFactor::Value(BlendValue::SourceAlpha), BlendFactor::One)),
+            composite: material(blend(BlendFactor::One, BlendFactor::OneMinusValue(BlendValue::SourceAlpha))),
+            size,
+            last: Vec::new(),
+        };
+        exposure.clear_texture();
+        exposure
+    }
+
+    // starts the exposure again from nothing
+    // clearing the texture throws away anything drawn so far this frame, so
+    // this and making a new one have to happen before anything is drawn
+    pub fn clear(&mut self) {
+        self.clear_texture();
+        self.last.clear();
+    }
+
+    fn clear_texture(&self) {
+        self.use_target();
+        clear_background(Color::new(0.0, 0.0, 0.0, 0.0));
+        set_default_camera();
+    }
+
+    // draws onto the texture instead of the screen, until the default camera is set again
+    // a positive y zoom, unlike the screen's, keeps the texture the right way up when it's drawn
+    fn use_target(&self) {
+        let (w, h) = self.size;
+        set_camera(&Camera2D {
+            target: vec2(w / 2.0, h / 2.0),
+            zoom: vec2(2.0 / w, 2.0 / h),
+            render_target: Some(self.target),
+            ..Default::default()
+        });
+    }
+
+    // a new size of window starts it again, as the old paths wouldn't line up
+    pub fn fit_screen(&mut self) {
+        let size = (screen_width(), screen_height());
+        if size != self.size {
+            self.target.delete();
+            self.target = screen_target(size);
+            self.size = size;
+            self.clear();
+        }
+    }
+
+    // adds where the bobs moved since the last frame onto the texture
+    pub fn add(&mut self, dp_vec: &[DoublePendulum], colors: &[Color], camera: &Camera, thickness: f32, inner: bool) {
+        self.last.resize(dp_vec.len(), None);
+
+        self.use_target();
+        gl_use_material(self.additive);
+        for ((dp, color), last) in dp_vec.iter().zip(colors.iter()).zip(self.last.iter_mut()) {
+            let (_, p1, p2) = render::joint_positions(dp, camera);
+            if !(p2.0.is_finite() && p2.1.is_finite()) {
+                *last = None;
+                continue;
+            }
+            let color = Color { a: color.a * EXPOSURE, ..*color };
+            // a bob that hasn't moved would have a line with no direction
+            if let Some((l1, l2)) = *last {
+                if l2 != p2 {
+                    draw_line(l2.0, l2.1, p2.0, p2.1, thickness, color);
+                }
+                if inner && l1 != p1 {
+                    draw_line(l1.0, l1.1, p1.0, p1.1, thickness, color);
+                }
+            }
+            *last = Some((p1, p2));
+        }
+        gl_use_default_material();
+        set_default_camera();
+    }
+
+    // draws the exposure onto the screen, under whatever is drawn after it
+    pub fn draw(&self) {
+        gl_use_material(self.composite);
+        draw_texture_ex(self.target.texture, 0.0, 0.0, WHITE, DrawTextureParams {
+            dest_size: Some(vec2(self.size.0, self.size.1)),
+            ..Default::default()
+        });
+        gl_use_default_material();
+    }
+}
+
+
+// the texture and shaders stay on the gpu until they're deleted
+impl Drop for Exposure {
+    fn drop(&mut self) {
+        self.target.delete();
+        self.additive.delete();
+        self.composite.delete();
+    }
+}
+
+// a texture to draw onto with a pixel for every pixel of the screen
+fn screen_target(size: (f32, f32)) -> RenderTarget {
+    let target = render_target(size.0 as u32, size.1 as u32);
+    target.texture.set_filter(FilterMode::Nearest);
+    target
+}
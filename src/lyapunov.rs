@@ -0,0 +1,104 @@
+// lyapunov.rs
+
+// estimates the largest lyapunov exponent of the fan, which measures how
+// quickly two pendulums that started almost together move apart
+// the separation grows like d0 * e^(λt), so λ is the slope of ln(d / d0) over time
+
+use crate::pendulum::{DoublePendulum, wrap_angle};
+
+
+// once the pendulums are this far apart in state space they are no longer
+// close, and the separation stops growing exponentially
+pub const SATURATION: f64 = 1.0;
+
+
+// the distance between two pendulums in state space (θ1, θ2, ω1, ω2)
+// differences in angle are wrapped so a full turn isn't counted as a separation
+pub fn separation(a: &DoublePendulum, b: &DoublePendulum) -> f64 {
+    state_distance(a.state(), b.state())
+}
+
+// the same distance, between two states (θ1, θ2, ω1, ω2)
+pub fn state_distance(a: (f64, f64, f64, f64), b: (f64, f64, f64, f64)) -> f64 {
+    (
+        wrap_angle(a.0 - b.0).powi(2) + wrap_angle(a.1 - b.1).powi(2)
+        + (a.2 - b.2).powi(2) + (a.3 - b.3).powi(2)
+    ).sqrt()
+}
+
+
+// a least squares fit of ln(d / d0) against time
+pub struct Lyapunov {
+    // the time and separation when the estimate started
+    t0: f64,
+    d0: f64,
+    saturated: bool,
+    n: f64,
+    sum_t: f64,
+    sum_y: f64,
+    sum_tt: f64,
+    sum_ty: f64,
+}
+
+impl Lyapunov {
+    pub fn new(t0: f64, a: &DoublePendulum, b: &DoublePendulum) -> Lyapunov {
+        Lyapunov { t0, d0: separation(a, b), saturated: false, n: 0.0, sum_t: 0.0, sum_y: 0.0, sum_tt: 0.0, sum_ty: 0.0 }
+    }
+
+    // adds the separation of the pendulums at time t to the fit
+    // nothing is added once the separation has saturated
+    pub fn record(&mut self, t: f64, a: &DoublePendulum, b: &DoublePendulum) {
+        let d = separation(a, b);
+        if self.saturated || self.d0 <= 0.0 || d <= 0.0 {
+            return;
+        }
+        if d >= SATURATION {
+            self.saturated = true;
+            return;
+        }
+
+        let (t, y) = (t - self.t0, (d / self.d0).ln());
+        self.n += 1.0;
+        self.sum_t += t;
+        self.sum_y += y;
+        self.sum_tt += t * t;
+        self.sum_ty += t * y;
+    }
+
+    // the slope of the fit, if there are enough points for one
+    pub fn estimate(&self) -> Option<f64> {
+        let denominator = self.n * self.sum_tt - self.sum_t * self.sum_t;
+        if self.n < 2.0 || denominator <= 0.0 {
+            return None;
+        }
+        Some((self.n * self.sum_ty - self.sum_t * self.sum_y) / denominator)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn a_chaotic_start_has_a_positive_exponent() {
+        let (theta1, theta2) = (170f64.to_radians(), 100f64.to_radians());
+        let mut a = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, theta1, theta2);
+        let mut b = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, theta1, theta2 + 1e-9);
+        let mut lyapunov = Lyapunov::new(0.0, &a, &b);
+        let dt = 1.0 / 240.0;
+        for i in 1..=240 * 20 {
+            a.update(dt);
+            b.update(dt);
+            lyapunov.record(i as f64 * dt, &a, &b);
+        }
+        assert!(lyapunov.estimate().unwrap() > 0.5);
+    }
+
+    #[test]
+    fn angles_a_full_turn_apart_are_not_separated() {
+        let a = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 3.1, -3.1);
+        let b = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 3.1 - std::f64::consts::TAU, -3.1 + std::f64::consts::TAU);
+        assert!(separation(&a, &b) < 1e-12);
+    }
+}
@@ -3,35 +3,45 @@
 // hides console on release
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
-use std::collections::LinkedList;
+use std::collections::VecDeque;
 
 use macroquad::prelude::*;
 // use macroquad::input::{is_key_down, is_key_pressed};
-use colorsys::{Rgb, Hsl};
+use clap::Parser;
+use egui_macroquad::egui;
+
+mod exposure;
+mod lyapunov;
+mod pendulum;
+mod render;
+mod replay;
+
+use pendulum::DoublePendulum;
+use render::Camera;
 
 
 // a module to deal with the fps
 pub mod fps {
-    use std::time::{SystemTime, UNIX_EPOCH};
-    use std::collections::LinkedList;
+    use std::time::Instant;
+    use std::collections::VecDeque;
 
     // FPS counter struct
     pub struct FPS {
-        frame_times: LinkedList<u128>,
+        frame_times: VecDeque<Instant>,
         max_frames: usize,
         frame: usize,
     }
 
     impl FPS {
         pub fn new(max_frames: usize) -> FPS {
-            FPS { frame_times: LinkedList::new(), max_frames, frame: 0 }
+            FPS { frame_times: VecDeque::with_capacity(max_frames + 1), max_frames, frame: 0 }
         }
 
         // add the current frame time and remove the oldest frame if there are
         // too many
         // should be called every frame as well as before fps
         pub fn update(&mut self) {
-            self.frame_times.push_back(FPS::now());
+            self.frame_times.push_back(Instant::now());
             if self.frame_times.len() > self.max_frames {
                 self.frame_times.pop_front();
             }
@@ -44,13 +54,13 @@ pub mod fps {
             if self.frame_times.len() < 2 {
                 0
             } else {
-                let diff = 
-                    self.frame_times.back().unwrap() - 
-                    self.frame_times.front().unwrap();
-                if diff == 0 { 
+                let diff = self.frame_times.back().unwrap()
+                    .duration_since(*self.frame_times.front().unwrap())
+                    .as_secs_f64();
+                if diff == 0.0 { 
                     0 
                 } else {
-                    ((self.frame_times.len() as f64) / (diff as f64) * 1000.0) as usize
+                    ((self.frame_times.len() as f64) / diff) as usize
                 }
             }
         }
@@ -60,261 +70,2063 @@ pub mod fps {
             self.frame
         }
 
-        // gets the current time
-        fn now() -> u128 {
-            let start = SystemTime::now();
-            start
-                .duration_since(UNIX_EPOCH)
-                .expect("Time went backwards")
-                .as_millis()
+        // the time between each pair of consecutive stored frames in
+        // milliseconds, from oldest to newest
+        pub fn frame_ms(&self) -> impl Iterator<Item = f64> + '_ {
+            self.frame_times.iter().zip(self.frame_times.iter().skip(1))
+                .map(|(a, b)| b.duration_since(*a).as_secs_f64() * 1000.0)
+        }
+
+        // the average, shortest, and longest frame times in milliseconds
+        // all are 0 until there are two frames
+        pub fn avg_ms(&self) -> f64 {
+            let count = self.frame_times.len().saturating_sub(1);
+            if count == 0 { 0.0 } else { self.frame_ms().sum::<f64>() / count as f64 }
+        }
+
+        pub fn min_ms(&self) -> f64 {
+            self.frame_ms().reduce(f64::min).unwrap_or(0.0)
+        }
+
+        pub fn max_ms(&self) -> f64 {
+            self.frame_ms().reduce(f64::max).unwrap_or(0.0)
+        }
+
+        // the frame time that p percent of frames were at least as fast as,
+        // ex, percentile(99.0) shows the stutters that the average hides
+        pub fn percentile(&self, p: f64) -> f64 {
+            let mut sorted: Vec<f64> = self.frame_ms().collect();
+            if sorted.is_empty() {
+                return 0.0;
+            }
+            sorted.sort_by(f64::total_cmp);
+            let rank = (p.clamp(0.0, 100.0) / 100.0 * sorted.len() as f64).ceil() as usize;
+            sorted[rank.clamp(1, sorted.len()) - 1]
         }
     }
 }
 
 
-// double pendulum state
-// theta1 and theta2 are the inner angles of the pendulum
-struct DoublePendulum {
-    origin_x: f64,
-    origin_y: f64,
-    length: f64,
-    theta1: f64,
-    theta2: f64,
-    mass: f64,
-    angular1: f64,
-    angular2: f64,
-    color: macroquad::color::Color,
-    prev_angles: LinkedList<(f64, f64)>,
-}
+// a module to load the simulation settings
+pub mod config {
+    use serde::{Deserialize, Serialize};
+    use clap::{Parser, ValueEnum};
 
-impl DoublePendulum {
-    fn new(origin_x: f64, origin_y: f64, length: f64, theta1: f64, theta2: f64, color: macroquad::color::Color) -> DoublePendulum {
-        DoublePendulum { origin_x, origin_y, length, theta1, theta2, mass: 1.0, angular1: 0.0, angular2: 0.0, color, prev_angles: LinkedList::new() }
+    // which of the starting angles the offset between pendulums is added to
+    #[derive(Serialize, Deserialize, ValueEnum, Debug, Clone, Copy, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    pub enum OffsetAngle {
+        First,
+        Second,
+        Both,
     }
 
-    fn draw(&self) {
-        let radius = 8.0;
-        let thickness = 7.0;
+    impl OffsetAngle {
+        pub fn first(self) -> bool {
+            self != OffsetAngle::Second
+        }
 
-        // let line_color = Color::new(0.7, 0.7, 0.7, 0.5);
-        // let node_color = Color::new(0.0, 0.0, 0.0, 1.0);
+        pub fn second(self) -> bool {
+            self != OffsetAngle::First
+        }
+    }
 
-        // let line_color = Color::new(0.08, 0.05, 0.05, 1.0);
-        // let node_color = Color::new(0.08, 0.05, 0.05, 1.0);
+    // how the starting angles of the fan are spread out
+    #[derive(Serialize, Deserialize, ValueEnum, Debug, Clone, Copy, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    pub enum FanMode {
+        // each pendulum starts offset a little further than the last
+        Linear,
+        // normally distributed around the first pendulum, with a standard deviation of spread
+        Gaussian,
+        // evenly distributed up to spread either side of the first pendulum
+        Uniform,
+    }
 
-        let line_color = self.color;
-        let node_color = self.color;
+    // what happens to a pendulum that has spun too fast for too long
+    #[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    pub enum EscapeAction {
+        // counted in the hud, but left alone
+        Flag,
+        // started again from where it started the first time
+        Reset,
+        // stopped where it is, like a pendulum that diverged
+        Freeze,
+    }
 
-        let ox = self.origin_x as f32;
-        let oy = self.origin_y as f32;
-        let dx1 = self.dx1() as f32;
-        let dy1 = self.dy1() as f32;
-        let dx2 = self.dx2() as f32;
-        let dy2 = self.dy2() as f32;
+    // the settings of a run, any that are missing from the file
+    // fall back to their defaults
+    // angles are in degrees
+    #[derive(Serialize, Deserialize, Debug, Clone)]
+    #[serde(default)]
+    pub struct Config {
+        pub dp_count: usize,
+        // the point the pendulums hang from, the center of the window if not set
+        pub origin: Option<(f64, f64)>,
+        pub length1: f64,
+        pub length2: f64,
+        pub mass1: f64,
+        pub mass2: f64,
+        pub gravity: f64,
+        // how far gravity is turned from straight down, in degrees, a positive
+        // angle pulls the pendulums to the right
+        pub gravity_angle: f64,
+        pub damping: f64,
+        // linear to slow the arms by damping, for friction at the joints, or
+        // quadratic to slow them by drag_coeff, for air resistance
+        pub drag: crate::pendulum::Drag,
+        pub drag_coeff: f64,
+        // each physics step is split into this many integration steps
+        pub substeps: usize,
+        // the longest single integration step, in seconds, None or inf never splits them
+        pub max_step: Option<f64>,
+        // the physics steps per second of simulated time, and the most frames
+        // drawn per second of real time, which doesn't change the physics
+        pub physics_rate: f64,
+        pub max_fps: Option<f64>,
+        // how the pendulums are stepped, euler, semi_implicit_euler, rk4 or dormand_prince
+        pub integrator: crate::pendulum::Integrator,
+        // the largest error allowed in each substep of dormand_prince, which
+        // takes as many substeps as that needs
+        pub tolerance: f64,
+        // runs one pendulum with each integrator from the same start instead of a fan
+        pub compare_integrators: bool,
+        // the pivot moves up and down as drive_amp * sin(drive_freq * t)
+        // in units of length, and radians per second
+        pub drive_amp: f64,
+        pub drive_freq: f64,
+        pub angle1: f64,
+        pub angle2: f64,
+        // the starting angular velocities of every pendulum, in degrees per second
+        pub omega1: f64,
+        pub omega2: f64,
+        // the second angle is randomized by up to this much either way
+        pub angle2_jitter: f64,
+        // a pendulum with an angular velocity above escape_speed, in degrees
+        // per second, for longer than escape_after seconds has run away and
+        // is dealt with by escape_action, there's no limit if it isn't set
+        pub escape_speed: Option<f64>,
+        pub escape_after: f64,
+        pub escape_action: EscapeAction,
+        // K changes both angular velocities of a pendulum by up to this much, in degrees per second
+        pub nudge: f64,
+        // how much the angle or angles offset_angle picks increase from one
+        // pendulum to the next, in degrees
+        pub offset: f64,
+        pub offset_angle: OffsetAngle,
+        pub fan_mode: FanMode,
+        // how far the random fans spread out, in degrees
+        pub spread: f64,
+        pub max_trace: usize,
+        // the amount of positions added to each trace per second of simulated time
+        pub trace_rate: f64,
+        // how the pendulums are colored, hsl, viridis, turbo or grayscale
+        pub colormap: crate::render::Colormap,
+        pub background: [f32; 4],
+        // how thick the rods and the newest part of the traces are, and
+        // the radius of a bob with a mass of 1, in pixels
+        pub rod_thickness: f32,
+        pub trace_thickness: f32,
+        // how much thinner each segment of a trace is than the one before, if
+        // not set it thins to a tenth at the oldest end whatever its length
+        pub trace_decay: Option<f64>,
+        // draws only every trace_stride-th position of the traces, for speed
+        pub trace_stride: usize,
+        pub node_radius: f32,
+        // how the traces fade out towards their oldest end, off, linear or exponential
+        pub trace_fade: crate::render::Fade,
+        // draws smoother bobs with shadows and rounded rods, for screenshots
+        pub pretty: bool,
+        // builds up where the bobs have been for as long as it runs, A toggles it
+        pub long_exposure: bool,
+        // uses a near black background instead, D toggles it
+        pub dark_mode: bool,
+        // the random number generator seed, taken from the clock if not set
+        pub seed: Option<u64>,
+        // whether to record the state of the first pendulum
+        pub record: bool,
+        // saves this many frames to the frames directory then quits, each
+        // frame advancing the simulation by exactly record_dt
+        pub record_frames: Option<usize>,
+        pub record_dt: f64,
+        // draws this many frames then quits, printing the frame rate and how
+        // long the traces took to draw, to compare changes to the drawing
+        pub bench_frames: Option<usize>,
+        // the window the simulation opens in, these can't change while running
+        // msaa is the amount of samples per pixel, which smooths the edges
+        pub window_width: i32,
+        pub window_height: i32,
+        pub fullscreen: bool,
+        pub high_dpi: bool,
+        pub msaa: i32,
+    }
 
-        draw_line(ox, oy, ox + dx1, oy + dy1, thickness, line_color);
-        draw_line(ox + dx1, oy + dy1, ox + dx2, oy + dy2, thickness, line_color);
-        draw_circle(ox, oy, radius, node_color);
-        draw_circle(ox + dx1, oy + dy1, radius, node_color);
-        draw_circle(ox + dx2, oy + dy2, radius, node_color);
-        // draw_circle(ox + dx2, oy + dy2, radius, self.color);
+    impl Default for Config {
+        fn default() -> Config {
+            Config {
+                dp_count: 300,
+                origin: None,
+                length1: 1.0,
+                length2: 1.0,
+                mass1: 1.0,
+                mass2: 1.0,
+                gravity: crate::pendulum::GRAVITY,
+                gravity_angle: 0.0,
+                damping: 0.0,
+                drag: crate::pendulum::Drag::Linear,
+                drag_coeff: 0.0,
+                substeps: 1,
+                max_step: Some(0.02),
+                physics_rate: 240.0,
+                max_fps: None,
+                integrator: crate::pendulum::Integrator::Rk4,
+                tolerance: 1e-9,
+                compare_integrators: false,
+                drive_amp: 0.0,
+                drive_freq: 0.0,
+                angle1: 180.0,
+                angle2: 180.0,
+                omega1: 0.0,
+                omega2: 0.0,
+                angle2_jitter: 1.0,
+                nudge: 0.5,
+                escape_speed: None,
+                escape_after: 2.0,
+                escape_action: EscapeAction::Flag,
+                offset: 0.0001,
+                offset_angle: OffsetAngle::Second,
+                fan_mode: FanMode::Linear,
+                spread: 0.03,
+                max_trace: 150,
+                trace_rate: 240.0,
+                colormap: crate::render::Colormap::Hsl,
+                background: [0.95, 0.95, 0.95, 1.0],
+                rod_thickness: 7.0,
+                trace_thickness: 10.0,
+                trace_decay: None,
+                trace_stride: 1,
+                node_radius: 8.0,
+                trace_fade: crate::render::Fade::Linear,
+                pretty: false,
+                long_exposure: false,
+                dark_mode: false,
+                seed: None,
+                record: false,
+                record_frames: None,
+                bench_frames: None,
+                record_dt: 1.0 / 60.0,
+                window_width: DEFAULT_WINDOW_SIZE,
+                window_height: DEFAULT_WINDOW_SIZE,
+                fullscreen: false,
+                high_dpi: false,
+                msaa: 4,
+            }
+        }
     }
 
-    fn draw_trace(&self) {
-        // let trace_color = Color::new(0.3, 0.3, 1.0, 1.0);
-        // let trace_color = Color::new(0.545, 0.867, 0.945, 0.25);
-        let trace_color = self.color;
+    // more pendulums than this won't run smoothly, so a warning is printed
+    pub const SOFT_MAX_COUNT: usize = 5000;
+    // the pendulum count is clamped to this
+    pub const HARD_MAX_COUNT: usize = 100_000;
+    // the most trace points kept across every pendulum, about 80MB
+    pub const MAX_TRACE_POINTS: usize = 2_000_000;
+    // the window can be between these sizes on each side, in pixels, any
+    // other size is probably a typo and the default is used instead
+    pub const MIN_WINDOW_SIZE: i32 = 100;
+    pub const MAX_WINDOW_SIZE: i32 = 16384;
+    pub const DEFAULT_WINDOW_SIZE: i32 = 600;
+    // shorter rods than this divide the accelerations by nearly nothing
+    pub const MIN_LENGTH: f64 = 0.01;
+    // a tighter tolerance than this needs so many substeps a frame never ends
+    pub const MIN_TOLERANCE: f64 = 1e-12;
+    // frames any further apart than this would look like the window froze
+    pub const MIN_FPS: f64 = 1.0;
 
-        let ox = self.origin_x as f32;
-        let oy = self.origin_y as f32;
+    impl Config {
+        // keeps the pendulum count and the memory used by the traces bounded,
+        // so a huge count slows things down instead of freezing the window
+        pub fn limit(&mut self) {
+            if self.dp_count > HARD_MAX_COUNT {
+                eprintln!("{} pendulums is too many, using {}", self.dp_count, HARD_MAX_COUNT);
+                self.dp_count = HARD_MAX_COUNT;
+            } else if self.dp_count > SOFT_MAX_COUNT {
+                eprintln!("Warning: {} pendulums may run slowly, {} or fewer is recommended", self.dp_count, SOFT_MAX_COUNT);
+            }
 
-        let mut thickness = 10.0;
-        let angle_zip = self.prev_angles.iter().zip(self.prev_angles.iter().skip(1));
-        for (i, j) in angle_zip {
-            let (a1, a2) = i;
-            let (b1, b2) = j;
+            let max_trace = MAX_TRACE_POINTS / self.dp_count.max(1);
+            if self.max_trace > max_trace {
+                eprintln!("Shortening the traces from {} to {} to fit {} pendulums", self.max_trace, max_trace, self.dp_count);
+                self.max_trace = max_trace;
+            }
 
-            let t1x = self.length * 100.0 * (a1.sin() + a2.sin());
-            let t1y = self.length * 100.0 * (a1.cos() + a2.cos());
+            for length in [&mut self.length1, &mut self.length2] {
+                if length.is_nan() || *length < MIN_LENGTH {
+                    eprintln!("A rod of length {} is too short, using {}", length, MIN_LENGTH);
+                    *length = MIN_LENGTH;
+                }
+            }
 
-            let t2x = self.length * 100.0 * (b1.sin() + b2.sin());
-            let t2y = self.length * 100.0 * (b1.cos() + b2.cos());
+            if self.tolerance.is_nan() || self.tolerance < MIN_TOLERANCE {
+                eprintln!("A tolerance of {} is too tight, using {}", self.tolerance, MIN_TOLERANCE);
+                self.tolerance = MIN_TOLERANCE;
+            }
 
-            draw_line(ox + t1x as f32, oy + t1y as f32, ox + t2x as f32, oy + t2y as f32, thickness, trace_color);
+            // a max_fps of 0 or less means there's no limit
+            if let Some(max_fps) = self.max_fps.filter(|&fps| fps.is_nan() || (fps > 0.0 && fps < MIN_FPS)) {
+                eprintln!("A max_fps of {} is too low, using {}", max_fps, MIN_FPS);
+                self.max_fps = Some(MIN_FPS);
+            }
 
-            thickness *= 0.985;
+            let sizes = MIN_WINDOW_SIZE..=MAX_WINDOW_SIZE;
+            if !sizes.contains(&self.window_width) || !sizes.contains(&self.window_height) {
+                eprintln!(
+                    "A {} by {} window isn't between {} and {} on each side, using {} by {}",
+                    self.window_width, self.window_height, MIN_WINDOW_SIZE, MAX_WINDOW_SIZE, DEFAULT_WINDOW_SIZE, DEFAULT_WINDOW_SIZE,
+                );
+                self.window_width = DEFAULT_WINDOW_SIZE;
+                self.window_height = DEFAULT_WINDOW_SIZE;
+            }
         }
-
     }
-}
 
+    // the config is in degrees, which are easier to write, while the physics
+    // is in radians, so everything with an angle in it is converted here on its way in
+    impl Config {
+        // the starting angles of the first pendulum, before any jitter
+        pub fn start_angles(&self) -> (f64, f64) {
+            radians((self.angle1, self.angle2))
+        }
 
-const g: f64 = 9.81;
-
+        pub fn start_angular_velocities(&self) -> (f64, f64) {
+            radians((self.omega1, self.omega2))
+        }
 
-// the motion of the pendulum is calculated using the Runge-Kutta method (RK4)
-// https://en.wikipedia.org/wiki/Double_pendulum
-// https://en.wikipedia.org/wiki/Runge%E2%80%93Kutta_methods
-// https://www.diego.assencio.com/?index=1500c66ae7ab27bb0106467c68feebc6
-impl DoublePendulum {
-    fn update(&mut self, timestep: f64) {
-        // if the timestep is too large, the solver becomes inaccurate 
-        // this can happen if the program lags, ex, the user moves the window
-        if timestep > 0.02 {
-            return;
+        pub fn gravity_tilt(&self) -> f64 {
+            self.gravity_angle.to_radians()
         }
 
-        // runge-kutta implementation
-        let current = DVec4::new(self.theta1, self.theta2, self.angular1, self.angular2);
+        // the most K changes each angular velocity by, in radians per second
+        pub fn nudge_radians(&self) -> f64 {
+            self.nudge.to_radians()
+        }
 
-        self.prev_angles.push_front((self.theta1, self.theta2));
-        if self.prev_angles.len() > 150 {
-            self.prev_angles.pop_back();
+        // in radians per second, if there is one
+        pub fn escape_speed_radians(&self) -> Option<f64> {
+            self.escape_speed.map(f64::to_radians)
         }
+    }
 
-        let k1 = self.runge_kutta_func(current);
-        let k2 = self.runge_kutta_func(current + (timestep / 2.0) * k1);
-        let k3 = self.runge_kutta_func(current + (timestep / 2.0) * k2);
-        let k4 = self.runge_kutta_func(current + timestep * k3);
+    // a pair of angles, or angular velocities, from degrees to radians
+    pub fn radians((a, b): (f64, f64)) -> (f64, f64) {
+        (a.to_radians(), b.to_radians())
+    }
 
-        let updated = current + (timestep / 6.0) * (k1 + 2.0 * k2 + 2.0 * k3 + k4);
+    // reads the config from a toml file
+    // uses the defaults if the file is missing, or if it can't be parsed
+    pub fn load_config(path: &str) -> Config {
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(_) => return Config::default(),
+        };
+        match toml::from_str(&contents) {
+            Ok(config) => config,
+            Err(e) => {
+                eprintln!("Failed to parse {}, using the default config: {}", path, e);
+                Config::default()
+            }
+        }
+    }
 
-        self.theta1 = updated.x;
-        self.theta2 = updated.y;
-        self.angular1 = updated.z;
-        self.angular2 = updated.w;
+    // command line overrides for the config
+    #[derive(Parser, Debug)]
+    #[command(about = "A double pendulum simulation")]
+    pub struct Args {
+        #[arg(long, help = "The amount of pendulums")]
+        pub count: Option<usize>,
+        #[arg(long, help = "The starting angle of the first rod, in degrees")]
+        pub angle1: Option<f64>,
+        #[arg(long, help = "The starting angle of the second rod, in degrees")]
+        pub angle2: Option<f64>,
+        #[arg(long, help = "The starting angular velocity of the first rod, in degrees per second")]
+        pub omega1: Option<f64>,
+        #[arg(long, help = "The starting angular velocity of the second rod, in degrees per second")]
+        pub omega2: Option<f64>,
+        #[arg(long, help = "The difference between neighbouring pendulums, in degrees, in the angle or angles --offset-angle picks")]
+        pub offset: Option<f64>,
+        #[arg(long, value_enum, help = "Which starting angle the offset is added to")]
+        pub offset_angle: Option<OffsetAngle>,
+        #[arg(long, value_enum, help = "How the starting angles of the fan are spread out")]
+        pub fan_mode: Option<FanMode>,
+        #[arg(long, help = "How far the gaussian and uniform fans spread out, in degrees")]
+        pub spread: Option<f64>,
+        #[arg(long, help = "The length of both rods")]
+        pub length: Option<f64>,
+        #[arg(long, help = "The gravitational acceleration")]
+        pub gravity: Option<f64>,
+        #[arg(long, help = "How far the second angle is randomized either way, in degrees")]
+        pub jitter: Option<f64>,
+        #[arg(long, help = "The amount of previous positions used to draw each trace")]
+        pub trace_len: Option<usize>,
+        #[arg(long, help = "The width of the window, in pixels")]
+        pub width: Option<i32>,
+        #[arg(long, help = "The height of the window, in pixels")]
+        pub height: Option<i32>,
+        #[arg(long, help = "The random number generator seed, to replay a run")]
+        pub seed: Option<u64>,
+        #[arg(long, help = "Record the first pendulum, E saves it to trajectory.csv")]
+        pub record: bool,
+        #[arg(long, help = "Save this many frames to frames/frame_00000.png and so on, then quit")]
+        pub record_frames: Option<usize>,
+        #[arg(long, help = "The simulated time between recorded frames")]
+        pub record_dt: Option<f64>,
+        #[arg(long, help = "Draw this many frames, then print the frame rate and the time spent drawing the traces and quit")]
+        pub bench_frames: Option<usize>,
+        #[arg(long, help = "Run one pendulum with each integrator from the same start, instead of a fan")]
+        pub compare_integrators: bool,
+        #[arg(long, help = "Simulate the first pendulum without a window, and write its trajectory to stdout as csv")]
+        pub headless: bool,
+        #[arg(long, default_value_t = 10000, help = "The amount of steps to simulate in headless mode")]
+        pub steps: usize,
+        #[arg(long, default_value_t = 1.0 / 240.0, help = "The timestep used in headless mode")]
+        pub dt: f64,
+        #[arg(long, value_name = "MILLIONS", help = "Time this many million steps of the first pendulum at --dt without a window, and print the steps per second")]
+        pub bench_physics: Option<f64>,
+        #[arg(long, value_name = "STEPS", help = "Time this many steps of the whole fan at --dt without a window, serially and, with the parallel feature, on every core")]
+        pub bench_fan: Option<usize>,
+        #[arg(long, help = "Play back a trajectory csv, saved with --record, instead of simulating the first pendulum")]
+        pub replay: Option<String>,
     }
 
-    // lots of stuff cancels since l1 = l2 and m1 = m2
-    fn a1(&self, theta1: f64, theta2: f64) -> f64 {
-        (theta1 - theta2).cos() / 2.0
+    impl Args {
+        // replaces the config values with any that were given on the command line
+        pub fn apply(&self, config: &mut Config) {
+            if let Some(count) = self.count {
+                config.dp_count = count;
+            }
+            if let Some(angle1) = self.angle1 {
+                config.angle1 = angle1;
+            }
+            if let Some(angle2) = self.angle2 {
+                config.angle2 = angle2;
+            }
+            if let Some(omega1) = self.omega1 {
+                config.omega1 = omega1;
+            }
+            if let Some(omega2) = self.omega2 {
+                config.omega2 = omega2;
+            }
+            if let Some(offset) = self.offset {
+                config.offset = offset;
+            }
+            if let Some(offset_angle) = self.offset_angle {
+                config.offset_angle = offset_angle;
+            }
+            if let Some(fan_mode) = self.fan_mode {
+                config.fan_mode = fan_mode;
+            }
+            if let Some(spread) = self.spread {
+                config.spread = spread;
+            }
+            if let Some(length) = self.length {
+                config.length1 = length;
+                config.length2 = length;
+            }
+            if let Some(gravity) = self.gravity {
+                config.gravity = gravity;
+            }
+            if let Some(jitter) = self.jitter {
+                config.angle2_jitter = jitter;
+            }
+            if let Some(trace_len) = self.trace_len {
+                config.max_trace = trace_len;
+            }
+            if let Some(width) = self.width {
+                config.window_width = width;
+            }
+            if let Some(height) = self.height {
+                config.window_height = height;
+            }
+            if let Some(seed) = self.seed {
+                config.seed = Some(seed);
+            }
+            if self.compare_integrators {
+                config.compare_integrators = true;
+            }
+            if self.record {
+                config.record = true;
+            }
+            if let Some(record_frames) = self.record_frames {
+                config.record_frames = Some(record_frames);
+            }
+            if let Some(record_dt) = self.record_dt {
+                config.record_dt = record_dt;
+            }
+            if let Some(bench_frames) = self.bench_frames {
+                config.bench_frames = Some(bench_frames);
+            }
+        }
     }
 
-    // lots of stuff cancels since l1 = l2 and m1 = m2
-    fn a2(&self, theta1: f64, theta2: f64) -> f64 {
-        (theta1 - theta2).cos()
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn a_sample_config_is_read_into_the_fields() {
+            let config: Config = toml::from_str(r#"
+                dp_count = 12
+                origin = [300.0, 200.0]
+                length1 = 1.5
+                length2 = 0.5
+                angle1 = 90.0
+                angle2 = 45.0
+                offset = 0.25
+                offset_angle = "both"
+                background = [0.1, 0.2, 0.3, 1.0]
+                max_trace = 400
+            "#).unwrap();
+            assert_eq!(config.dp_count, 12);
+            assert_eq!(config.origin, Some((300.0, 200.0)));
+            assert_eq!((config.length1, config.length2), (1.5, 0.5));
+            assert_eq!((config.angle1, config.angle2), (90.0, 45.0));
+            assert_eq!(config.offset, 0.25);
+            assert_eq!(config.offset_angle, OffsetAngle::Both);
+            assert_eq!(config.background, [0.1, 0.2, 0.3, 1.0]);
+            assert_eq!(config.max_trace, 400);
+        }
+
+        #[test]
+        fn missing_fields_fall_back_to_the_defaults() {
+            let config: Config = toml::from_str("dp_count = 3").unwrap();
+            let default = Config::default();
+            assert_eq!(config.dp_count, 3);
+            assert_eq!(config.angle1, default.angle1);
+            assert_eq!(config.max_trace, default.max_trace);
+            assert_eq!(config.background, default.background);
+        }
+
+        #[test]
+        fn the_example_config_parses() {
+            let path = concat!(env!("CARGO_MANIFEST_DIR"), "/config.toml");
+            toml::from_str::<Config>(&std::fs::read_to_string(path).unwrap()).unwrap();
+        }
+
+        #[test]
+        fn a_missing_file_gives_the_defaults() {
+            let config = load_config("there is no config here.toml");
+            assert_eq!(config.dp_count, Config::default().dp_count);
+        }
+
+        #[test]
+        fn a_tiny_max_fps_is_raised_to_the_minimum() {
+            for max_fps in [1e-300, f64::NAN] {
+                let mut config = Config { max_fps: Some(max_fps), ..Config::default() };
+                config.limit();
+                assert_eq!(config.max_fps, Some(MIN_FPS));
+            }
+            let mut config = Config { max_fps: Some(0.0), ..Config::default() };
+            config.limit();
+            assert_eq!(config.max_fps, Some(0.0));
+        }
     }
+}
+
+
 
-    fn f1(&self, theta1: f64, theta2: f64, angular1: f64, angular2: f64) -> f64 {
-        -(angular2 * angular2) * (theta1 - theta2).sin() / 2.0
-        - g / self.length * theta1.sin()
+
+// each pendulum is created with a slight offset in the second angle,
+// or the first, or both, to show the chaotic behavior
+// the angles are in degrees, like the config
+fn create_fan(config: &config::Config, angle1: f64, angle2: f64) -> Vec<DoublePendulum> {
+    let (theta1, theta2) = config::radians((angle1, angle2));
+    let (angular1, angular2) = config.start_angular_velocities();
+    if config.compare_integrators {
+        return pendulum::Integrator::ALL.iter()
+            .map(|&integrator| fan_pendulum(config, theta1, theta2, angular1, angular2).with_integrator(integrator))
+            .collect();
     }
+    fan_angles(config, theta1, theta2)
+        .into_iter()
+        .map(|(theta1, theta2)| fan_pendulum(config, theta1, theta2, angular1, angular2))
+        .collect()
+}
+
+// the fan from these angles, with the generator seeded first, so the random
+// fans are decided by the seed alone, however much the generator has been
+// used since, ex, by N or K
+fn seeded_fan(config: &config::Config, angle1: f64, angle2: f64, seed: u64) -> Vec<DoublePendulum> {
+    macroquad::rand::srand(seed);
+    create_fan(config, angle1, angle2)
+}
+
+// the starting angles of every pendulum in the fan, in radians
+fn fan_angles(config: &config::Config, theta1: f64, theta2: f64) -> Vec<(f64, f64)> {
+    (0..config.dp_count.max(1)).map(|i| offset_angles(config, theta1, theta2, i)).collect()
+}
 
-    fn f2(&self, theta1: f64, theta2: f64, angular1: f64, angular2: f64) -> f64 {
-        (angular1 * angular1) * (theta1 - theta2).sin()
-        - g / self.length * theta2.sin()
+// adds or removes pendulums from the end of the fan until there are dp_count
+// new pendulums carry on from where the last one is now, each offset a bit
+// more, so they join the fan in its current state instead of where it started
+fn resize_fan(dp_vec: &mut Vec<DoublePendulum>, config: &config::Config, angle1: f64, angle2: f64) {
+    let dp_count = config.dp_count.max(1);
+    if dp_vec.is_empty() {
+        *dp_vec = create_fan(config, angle1, angle2);
     }
+    dp_vec.truncate(dp_count);
 
-    fn g1(&self, t1: f64, t2: f64, ang1: f64, ang2: f64) -> f64 {
-        (self.f1(t1, t2, ang1, ang2) - self.a1(t1, t2) * self.f2(t1, t2, ang1, ang2)) /
-        (1.0 - self.a1(t1, t2)*self.a2(t1, t2))
+    let last = dp_vec.len() - 1;
+    let (theta1, theta2, angular1, angular2) = dp_vec[last].state();
+    let time = dp_vec[last].time;
+    for i in 1..=(dp_count - dp_vec.len()) {
+        let (theta1, theta2) = offset_angles(config, theta1, theta2, i);
+        dp_vec.push(joining_pendulum(config, theta1, theta2, angular1, angular2, time));
     }
+}
+
+// the second angle the fan starts from, in degrees, randomized by up to
+// angle2_jitter either way with the seeded generator, so a seed replays it
+fn jittered_angle2(config: &config::Config) -> f64 {
+    config.angle2 + macroquad::rand::gen_range(-config.angle2_jitter, config.angle2_jitter)
+}
+
+// the angles of the pendulum i places further along the fan, in radians
+// the random fans are drawn from the seeded generator, so a seed replays
+// them too, the first pendulum is never moved so it stays a reference
+fn offset_angles(config: &config::Config, theta1: f64, theta2: f64, i: usize) -> (f64, f64) {
+    let offset = match config.fan_mode {
+        _ if i == 0 => 0.0,
+        config::FanMode::Linear => config.offset * (i as f64),
+        config::FanMode::Gaussian => config.spread * gaussian(),
+        config::FanMode::Uniform => macroquad::rand::gen_range(-config.spread, config.spread),
+    }.to_radians();
+    (
+        if config.offset_angle.first() { theta1 + offset } else { theta1 },
+        if config.offset_angle.second() { theta2 + offset } else { theta2 },
+    )
+}
 
-    fn g2(&self, t1: f64, t2: f64, ang1: f64, ang2: f64) -> f64 {
-        (-self.a2(t1, t2) * self.f1(t1, t2, ang1, ang2) + self.f2(t1, t2, ang1, ang2)) /
-        (1.0 - self.a1(t1, t2)*self.a2(t1, t2))
+// a normally distributed random number, with a mean of 0 and a standard deviation of 1
+// https://en.wikipedia.org/wiki/Box%E2%80%93Muller_transform
+fn gaussian() -> f64 {
+    let u1 = macroquad::rand::gen_range(f64::EPSILON, 1.0);
+    let u2 = macroquad::rand::gen_range(0.0, 1.0);
+    (-2.0 * u1.ln()).sqrt() * (std::f64::consts::TAU * u2).cos()
+}
+
+// a pendulum joining a fan that has already run for time seconds, in radians
+fn joining_pendulum(config: &config::Config, theta1: f64, theta2: f64, angular1: f64, angular2: f64, time: f64) -> DoublePendulum {
+    let mut dp = fan_pendulum(config, theta1, theta2, angular1, angular2);
+    // so a driven pivot moves with the rest
+    dp.time = time;
+    dp
+}
+
+// the highlighted pendulum, or the first if none is or the fan has shrunk past it
+fn hero_index(hero: Option<usize>, len: usize) -> usize {
+    hero.filter(|&i| i < len).unwrap_or(0)
+}
+
+// a pendulum of the fan, in radians
+fn fan_pendulum(config: &config::Config, theta1: f64, theta2: f64, angular1: f64, angular2: f64) -> DoublePendulum {
+    DoublePendulum::builder()
+        .length(config.length1, config.length2)
+        .mass(config.mass1, config.mass2)
+        .angles(theta1, theta2)
+        .angular_velocities(angular1, angular2)
+        .max_trace(config.max_trace)
+        .build()
+        .with_gravity(config.gravity)
+        .with_gravity_angle(config.gravity_tilt())
+        .with_damping(config.damping)
+        .with_drag(config.drag)
+        .with_drag_coeff(config.drag_coeff)
+        .with_substeps(config.substeps)
+        .with_max_step(config.max_step)
+        .with_integrator(config.integrator)
+        .with_tolerance(config.tolerance)
+        .with_drive(config.drive_amp, config.drive_freq)
+        .with_trace_rate(config.trace_rate)
+}
+
+// steps every pendulum forward by dt
+// the pendulums don't affect each other, so with the parallel feature
+// they're split between threads, which gives the same result
+fn update_all(dp_vec: &mut [DoublePendulum], dt: f64) {
+    #[cfg(feature = "parallel")]
+    update_parallel(dp_vec, dt);
+    #[cfg(not(feature = "parallel"))]
+    update_serial(dp_vec, dt);
+}
+
+// one pendulum after another on this thread, used without the parallel
+// feature, and by --bench-fan to compare against
+#[cfg_attr(feature = "parallel", allow(dead_code))]
+fn update_serial(dp_vec: &mut [DoublePendulum], dt: f64) {
+    for dp in dp_vec.iter_mut() {
+        dp.update(dt);
     }
+}
 
-    fn runge_kutta_func(&self, params: DVec4) -> DVec4 {
-        DVec4::new(
-            params.z,
-            params.w,
-            self.g1(params.x, params.y, params.z, params.w),
-            self.g2(params.x, params.y, params.z, params.w),
-        )
+#[cfg(feature = "parallel")]
+fn update_parallel(dp_vec: &mut [DoublePendulum], dt: f64) {
+    use rayon::prelude::*;
+    dp_vec.par_iter_mut().for_each(|dp| dp.update(dt));
+}
+
+// the amount of physics steps of dt to take out of the time in the
+// accumulator, up to max_steps, any more time than that is dropped so a
+// slow frame doesn't leave the simulation behind for good
+// what's left is less than dt, and carried over to the next frame
+fn take_steps(accumulator: &mut f64, dt: f64, max_steps: Option<usize>) -> usize {
+    let mut steps = 0;
+    while *accumulator >= dt && max_steps.is_none_or(|max_steps| steps < max_steps) {
+        *accumulator -= dt;
+        steps += 1;
+    }
+    if *accumulator >= dt {
+        *accumulator %= dt;
     }
+    steps
 }
 
-// these functions get the endpoints of each rod
-// useful for drawing the double pendulum
-impl DoublePendulum {
-    fn dx1(&self) -> f64 {
-        self.length * 100.0 * self.theta1.sin()
+// the lyapunov estimate of a fan, starting at time t0
+// a fan of one pendulum has nothing to compare against
+fn fan_lyapunov(dp_vec: &[DoublePendulum], t0: f64) -> Option<lyapunov::Lyapunov> {
+    if dp_vec.len() < 2 {
+        return None;
     }
+    Some(lyapunov::Lyapunov::new(t0, &dp_vec[0], &dp_vec[1]))
+}
 
-    fn dy1(&self) -> f64 {
-        self.length * 100.0 * self.theta1.cos()
+// where the pendulums hang from, which follows the center of the window
+// as it's resized unless the config fixes it
+fn fan_origin(config: &config::Config) -> (f32, f32) {
+    match config.origin {
+        Some((x, y)) => (x as f32, y as f32),
+        None => (screen_width() / 2.0, screen_height() / 2.0),
     }
+}
 
-    fn dx2(&self) -> f64 {
-        self.length * 100.0 * (self.theta1.sin() + self.theta2.sin())
+// whether the window is fullscreen, which lasts across restarts
+// macroquad can't say what size the window was, so it's remembered here
+// to go back to afterwards
+struct Screen {
+    fullscreen: bool,
+    windowed_size: (f32, f32),
+}
+
+impl Screen {
+    fn toggle_fullscreen(&mut self) {
+        if self.fullscreen {
+            set_fullscreen(false);
+            request_new_screen_size(self.windowed_size.0, self.windowed_size.1);
+        } else {
+            self.windowed_size = (screen_width(), screen_height());
+            set_fullscreen(true);
+        }
+        self.fullscreen = !self.fullscreen;
     }
+}
 
-    fn dy2(&self) -> f64 {
-        self.length * 100.0 * (self.theta1.cos() + self.theta2.cos())
+// what the main view shows
+#[derive(Clone, Copy, PartialEq)]
+enum View {
+    Pendulum,
+    // theta1 against angular1
+    Phase,
+    Poincare,
+}
+
+impl View {
+    fn next(self) -> View {
+        match self {
+            View::Pendulum => View::Phase,
+            View::Phase => View::Poincare,
+            View::Poincare => View::Pendulum,
+        }
     }
 }
 
+// the most pendulums that can be added while running
+const MAX_COUNT: usize = 2000;
+
+// the most forward states kept to compare against, a minute of steps
+const MAX_FORWARD_PATH: usize = 240 * 60;
+// how far from the forward path running backwards can be while still retracing it
+const RETRACE_TOLERANCE: f64 = 0.01;
+
+// the most integration steps each physics step can be split into
+const MAX_SUBSTEPS: usize = 64;
+
+// how much slower the simulation runs while ALT is held
+const SLOW_MOTION: f64 = 0.1;
+
+// which bob of the first pendulum is being dragged by the mouse
+enum DragBob {
+    Inner,
+    Outer,
+}
 
-async fn run() {
+async fn run(config: &config::Config, seed: u64, replay: Option<&replay::Replay>, screen: &mut Screen) {
+    macroquad::rand::srand(seed);
     let mut fps_counter = fps::FPS::new(64);
-    let dp_count = 300;
 
-    let origin = (300.0, 300.0);
-    let length = 1.0;
+    // the settings can be changed while running, and are used for any
+    // pendulums that are created
+    let mut config = config.clone();
 
-    let angle1: f64 = 180.0;
-    let angle2: f64 = 180.0 + macroquad::rand::gen_range(-1.0 as f64, 1.0 as f64);
+    // the angles the fan starts from, in degrees like the config, so F2 and
+    // F5 give back exactly what was used
+    let mut angle1: f64 = config.angle1;
+    let mut angle2: f64 = jittered_angle2(&config);
+    // what the random offsets of the fan are drawn from, the seed of the run
+    // until N picks another, so F2 can give back the seed of the fan shown
+    let mut fan_seed = seed;
 
-    let offset = 0.0001;
+    // a replay plays back a single pendulum, any added to it are simulated
+    // from wherever it was when they were added
+    if replay.is_some() {
+        config.dp_count = 1;
+    }
+    if config.compare_integrators {
+        config.dp_count = pendulum::Integrator::ALL.len();
+    }
+    let mut dp_vec = seeded_fan(&config, angle1, angle2, fan_seed);
+    if let Some(replay) = replay {
+        dp_vec[0].seek(replay.start(), replay.state_at(replay.start()));
+    }
+    let mut colors = render::fan_colors(dp_vec.len(), config.colormap, render::fan_alpha(config.dark_mode));
 
-    // each pendulum is created with a slight offset in the second angle
-    // to show the chaotic behavior
-    let mut dp_vec: Vec<DoublePendulum> = Vec::new();
-    for i in 0..dp_count {
-        let c = Rgb::from(Hsl::new(255.0 * (i as f64) / ((dp_count - 1) as f64), 100.0, 80.0, None));
+    // the physics is stepped with a fixed timestep so that the simulation
+    // runs at the same speed and gives the same result at any frame rate
+    let physics_dt = 1.0 / if config.physics_rate > 0.0 { config.physics_rate } else { 240.0 };
+    // if a frame takes too long, only enough steps for a frame of 1/30s, or
+    // of 1/max_fps if that's longer, are simulated and the rest of the
+    // elapsed time is dropped so the simulation can catch up
+    // a max_fps under 30 spaces the frames out by more than 1/30s on
+    // purpose, which would otherwise run slower than real time
+    let longest_frame = config.max_fps.filter(|&fps| fps > 0.0).map_or(1.0 / 30.0, |fps| (1.0 / fps).max(1.0 / 30.0));
+    let max_substeps = (longest_frame / physics_dt).ceil() as usize;
+    // the physics steps taken per second of real time, counted over about a second
+    let mut steps_per_second = 0.0;
+    let mut steps_counted_from = (std::time::Instant::now(), 0);
+    // when the last frame was shown, to hold the frame rate under max_fps
+    let mut last_frame = std::time::Instant::now();
+    let mut accumulator = 0.0;
+    // how fast the simulation runs compared to real time
+    let mut speed: f64 = 1.0;
+    // 1 when time runs forwards, -1 when it runs backwards
+    let mut time_dir: f64 = 1.0;
+    // the states the first pendulum passed through going forwards, the
+    // newest last, so running backwards can check it's retracing them
+    let mut forward_path: VecDeque<(f64, f64, f64, f64)> = VecDeque::new();
+    // how long running backwards has stayed on the forward path, and
+    // whether it has strayed from it yet
+    let mut retrace_time = 0.0;
+    let mut retraced = true;
 
-        dp_vec.push(
-            DoublePendulum::new(
-                origin.0, origin.1, length,
-                angle1.to_radians(),
-                (angle2 + offset * (i as f64)).to_radians(),
-                Color::new(c.red() as f32 / 255.0, c.green() as f32 / 255.0, c.blue() as f32 / 255.0, 0.25),
-            )
-        );
+    // the energy of the first pendulum should stay constant, so any drift
+    // from its starting energy shows how inaccurate the solver is being
+    let mut initial_energy = dp_vec[0].total_energy();
+    // only conserved without gravity, damping or a drive, but still shows how it changes
+    let mut initial_momentum = dp_vec[0].angular_momentum();
+    // how quickly the first two pendulums of the fan diverge
+    let mut lyapunov = fan_lyapunov(&dp_vec, 0.0);
+
+    let mut paused = false;
+    let mut dragging: Option<DragBob> = None;
+    let mut show_trace = true;
+    let mut trace_inner = false;
+    let mut speed_colors = false;
+    // colors each pendulum by how far it has moved away from the first
+    let mut divergence_colors = false;
+    let mut view = View::Pendulum;
+    // draws where the fan started under the pendulums
+    let mut show_ghost = false;
+    // draws the circle the outer bobs can reach
+    let mut show_reach = false;
+    // the paths of the bobs since the long exposure was started, if it has been
+    let mut exposure = if config.long_exposure { Some(exposure::Exposure::new()) } else { None };
+    // a small phase portrait of the hero, or the first pendulum
+    let mut show_phase_inset = false;
+    // the pendulum drawn opaque over the rest of the fan, if any
+    let mut hero: Option<usize> = None;
+    let mut camera = Camera::new();
+    let mut last_mouse = mouse_position();
+    let mut show_panel = false;
+    let mut show_fps_graph = true;
+    let mut show_histogram = false;
+    // the total amount of physics steps taken
+    let mut steps: usize = 0;
+    // the total amount of simulated time
+    let mut sim_time = 0.0;
+
+    // the state of the first pendulum after every step, if recording
+    let mut trajectory: Vec<(f64, f64, f64, f64, f64)> = Vec::new();
+    if config.record {
+        prevent_quit();
     }
 
-    macroquad::window::request_new_screen_size(600.0, 600.0);
-    next_frame().await;
+    // the frames saved so far, if recording frames
+    // recording stops if the directory can't be made or a frame can't be saved
+    let mut record_frames = config.record_frames;
+    let mut recorded_frames = 0;
+    if record_frames.is_some() {
+        if let Err(e) = std::fs::create_dir_all("frames") {
+            eprintln!("Failed to create the frames directory, not recording: {}", e);
+            record_frames = None;
+        }
+    }
+
+    // the frames drawn and the time spent drawing traces, if benchmarking the drawing
+    let bench_start = std::time::Instant::now();
+    let mut benched_frames = 0;
+    let mut trace_time = std::time::Duration::ZERO;
 
     // update loop
-    let bg_color = Color::new(0.95, 0.95, 0.95, 1.0);
     loop {
-        clear_background(bg_color);
+        if is_key_pressed(KeyCode::D) {
+            config.dark_mode = !config.dark_mode;
+            colors = render::fan_colors(dp_vec.len(), config.colormap, render::fan_alpha(config.dark_mode));
+        }
+        let [r, g, b, a] = config.background;
+        let background = if config.dark_mode { render::DARK_BACKGROUND } else { Color::new(r, g, b, a) };
+        let text_color = render::text_color(background);
+        clear_background(background);
 
         // restart if the R key was pressed
         if is_key_pressed(KeyCode::R) {
             return;
         }
 
+        camera.origin = fan_origin(&config);
+        camera.fit = render::fit_scale(screen_width(), screen_height(), config.length1 + config.length2 + config.drive_amp.abs());
+
+        // the origin and scale follow the new size of the window from the next frame
+        if is_key_pressed(KeyCode::F11) {
+            screen.toggle_fullscreen();
+        }
+
+        // the control panel edits the config directly, and the changes are
+        // applied to the pendulums afterwards
+        if is_key_pressed(KeyCode::F1) {
+            show_panel = !show_panel;
+        }
+        let mut physics_changed = false;
+        let mut trace_changed = false;
+        let mut rebuild = false;
+        let mut count_changed = false;
+        let mut ui_wants_mouse = false;
+        egui_macroquad::ui(|ctx| {
+            if show_panel {
+                egui::SidePanel::right("controls").show(ctx, |ui| {
+                    ui.heading("Controls");
+                    physics_changed |= ui.add(egui::Slider::new(&mut config.gravity, 0.0..=30.0).text("Gravity")).changed();
+                    physics_changed |= ui.add(egui::Slider::new(&mut config.gravity_angle, -180.0..=180.0).text("Gravity angle")).changed();
+                    physics_changed |= ui.add(egui::Slider::new(&mut config.damping, 0.0..=2.0).text("Damping")).changed();
+                    ui.horizontal(|ui| {
+                        ui.label("Drag");
+                        physics_changed |= ui.radio_value(&mut config.drag, pendulum::Drag::Linear, "Linear").changed();
+                        physics_changed |= ui.radio_value(&mut config.drag, pendulum::Drag::Quadratic, "Quadratic").changed();
+                    });
+                    physics_changed |= ui.add(egui::Slider::new(&mut config.drag_coeff, 0.0..=2.0).text("Drag coefficient")).changed();
+                    trace_changed |= ui.add(egui::Slider::new(&mut config.max_trace, 0..=1000).text("Trace length")).changed();
+                    count_changed |= ui.add(egui::Slider::new(&mut config.dp_count, 1..=MAX_COUNT).text("Pendulums")).changed();
+                    rebuild |= ui.add(
+                        egui::Slider::new(&mut config.offset, 0.000001..=0.01).logarithmic(true).text("Offset")
+                    ).changed();
+                    ui.horizontal(|ui| {
+                        ui.label("Offset angle");
+                        rebuild |= ui.radio_value(&mut config.offset_angle, config::OffsetAngle::First, "First").changed();
+                        rebuild |= ui.radio_value(&mut config.offset_angle, config::OffsetAngle::Second, "Second").changed();
+                        rebuild |= ui.radio_value(&mut config.offset_angle, config::OffsetAngle::Both, "Both").changed();
+                    });
+                    ui.horizontal(|ui| {
+                        ui.label("Fan");
+                        rebuild |= ui.radio_value(&mut config.fan_mode, config::FanMode::Linear, "Linear").changed();
+                        rebuild |= ui.radio_value(&mut config.fan_mode, config::FanMode::Gaussian, "Gaussian").changed();
+                        rebuild |= ui.radio_value(&mut config.fan_mode, config::FanMode::Uniform, "Uniform").changed();
+                    });
+                    rebuild |= ui.add(
+                        egui::Slider::new(&mut config.spread, 0.0001..=10.0).logarithmic(true).text("Spread")
+                    ).changed();
+                    ui.add(egui::Slider::new(&mut config.rod_thickness, 0.5..=20.0).text("Rod thickness"));
+                    ui.add(egui::Slider::new(&mut config.trace_thickness, 0.5..=20.0).text("Trace thickness"));
+                    ui.add(egui::Slider::new(&mut config.trace_stride, 1..=16).text("Trace stride"));
+                    ui.add(egui::Slider::new(&mut config.node_radius, 0.5..=20.0).text("Bob radius"));
+                    ui.horizontal(|ui| {
+                        ui.label("Trace fade");
+                        ui.radio_value(&mut config.trace_fade, render::Fade::Off, "Off");
+                        ui.radio_value(&mut config.trace_fade, render::Fade::Linear, "Linear");
+                        ui.radio_value(&mut config.trace_fade, render::Fade::Exponential, "Exponential");
+                    });
+                    ui.checkbox(&mut config.pretty, "Shadows and smooth bobs");
+                    rebuild |= ui.button("Reset").clicked();
+                });
+            }
+            ui_wants_mouse = ctx.wants_pointer_input() || ctx.is_pointer_over_area();
+        });
+
+        // zoom in and out around the origin with the scroll wheel
+        let (_, wheel) = mouse_wheel();
+        if wheel != 0.0 && !ui_wants_mouse {
+            camera.zoom = (camera.zoom * 1.1_f32.powf(wheel.signum())).clamp(0.25, 8.0);
+        }
+        // pan by dragging with the middle mouse button
+        let mouse = mouse_position();
+        if is_mouse_button_down(MouseButton::Middle) && !ui_wants_mouse {
+            camera.pan_x += mouse.0 - last_mouse.0;
+            camera.pan_y += mouse.1 - last_mouse.1;
+        }
+        last_mouse = mouse;
+        if is_key_pressed(KeyCode::Home) {
+            camera = Camera { origin: camera.origin, fit: camera.fit, ..Camera::new() };
+        }
+
+        if is_key_pressed(KeyCode::Space) {
+            paused = !paused;
+            dragging = None;
+        }
+        if is_key_pressed(KeyCode::T) {
+            show_trace = !show_trace;
+        }
+        if is_key_pressed(KeyCode::G) {
+            show_fps_graph = !show_fps_graph;
+        }
+        if is_key_pressed(KeyCode::H) {
+            show_histogram = !show_histogram;
+        }
+        if is_key_pressed(KeyCode::I) {
+            trace_inner = !trace_inner;
+        }
+        if is_key_pressed(KeyCode::S) {
+            speed_colors = !speed_colors;
+        }
+        if is_key_pressed(KeyCode::X) {
+            divergence_colors = !divergence_colors;
+            if !divergence_colors {
+                colors = render::fan_colors(dp_vec.len(), config.colormap, render::fan_alpha(config.dark_mode));
+            }
+        }
+        if is_key_pressed(KeyCode::C) {
+            config.colormap = config.colormap.next();
+            colors = render::fan_colors(dp_vec.len(), config.colormap, render::fan_alpha(config.dark_mode));
+        }
+        // C is taken by the colormap, so BACKSPACE wipes the traces, and the long exposure
+        if is_key_pressed(KeyCode::Backspace) {
+            for dp in dp_vec.iter_mut() {
+                dp.clear_trace();
+            }
+            if let Some(exposure) = exposure.as_mut() {
+                exposure.clear();
+            }
+        }
+        if is_key_pressed(KeyCode::A) {
+            exposure = match exposure {
+                Some(_) => None,
+                None => Some(exposure::Exposure::new()),
+            };
+        }
+        if let Some(exposure) = exposure.as_mut() {
+            exposure.fit_screen();
+        }
+        if is_key_pressed(KeyCode::Z) {
+            show_ghost = !show_ghost;
+        }
+        if is_key_pressed(KeyCode::L) {
+            show_reach = !show_reach;
+        }
+        if is_key_pressed(KeyCode::J) {
+            show_phase_inset = !show_phase_inset;
+        }
+        // TAB highlights each pendulum in turn, then none of them
+        if is_key_pressed(KeyCode::Tab) {
+            hero = match hero {
+                None => Some(0),
+                Some(i) if i + 1 < dp_vec.len() => Some(i + 1),
+                Some(_) => None,
+            };
+        }
+        if is_key_pressed(KeyCode::V) {
+            view = view.next();
+            dragging = None;
+        }
+        if is_key_pressed(KeyCode::M) {
+            view = if view == View::Poincare { View::Pendulum } else { View::Poincare };
+            dragging = None;
+        }
+
+        // while paused, the bobs of the first pendulum can be dragged to
+        // choose new starting angles for the whole fan
+        if paused && view == View::Pendulum {
+            let (mx, my) = mouse_position();
+            let dp = &mut dp_vec[0];
+            let ((ox, oy), joint, bob) = render::joint_positions(dp, &camera);
+
+            if is_mouse_button_pressed(MouseButton::Left) && !ui_wants_mouse {
+                let near = |(x, y): (f32, f32), mass: f64| {
+                    (mx - x).hypot(my - y) <= config.node_radius * mass.sqrt() as f32
+                };
+                // the outer bob is checked first since it's drawn on top
+                dragging = if near(bob, dp.mass2) {
+                    Some(DragBob::Outer)
+                } else if near(joint, dp.mass1) {
+                    Some(DragBob::Inner)
+                } else {
+                    None
+                };
+            }
+
+            // the angles are measured from straight down, like dx / dy
+            match dragging {
+                Some(DragBob::Inner) => dp.theta1 = ((mx - ox) as f64).atan2((my - oy) as f64),
+                Some(DragBob::Outer) => dp.theta2 = ((mx - joint.0) as f64).atan2((my - joint.1) as f64),
+                None => {}
+            }
+
+            if dragging.is_some() && is_mouse_button_released(MouseButton::Left) {
+                angle1 = dp.theta1.to_degrees();
+                angle2 = dp.theta2.to_degrees();
+                dp_vec = seeded_fan(&config, angle1, angle2, fan_seed);
+                colors = render::fan_colors(dp_vec.len(), config.colormap, render::fan_alpha(config.dark_mode));
+                initial_energy = dp_vec[0].total_energy();
+                initial_momentum = dp_vec[0].angular_momentum();
+                lyapunov = fan_lyapunov(&dp_vec, sim_time);
+                forward_path.clear();
+                dragging = None;
+            }
+        }
+
+        // shorten or lengthen the traces with the bracket keys
+        let trace_step = 10;
+        if is_key_pressed(KeyCode::LeftBracket) || is_key_pressed(KeyCode::RightBracket) {
+            if is_key_pressed(KeyCode::LeftBracket) {
+                config.max_trace = config.max_trace.saturating_sub(trace_step).max(trace_step);
+            } else {
+                config.max_trace = (config.max_trace + trace_step).min(1000);
+            }
+            trace_changed = true;
+        }
+        if trace_changed {
+            config.limit();
+            for dp in dp_vec.iter_mut() {
+                dp.set_max_trace(config.max_trace);
+            }
+        }
+
+        // halve or double the simulation speed with the left and right arrow
+        // keys, or tilt gravity with them while holding SHIFT
+        let shift = is_key_down(KeyCode::LeftShift) || is_key_down(KeyCode::RightShift);
+        let tilt_step = 5.0;
+        if is_key_pressed(KeyCode::Left) {
+            if shift {
+                config.gravity_angle = (config.gravity_angle - tilt_step).max(-180.0);
+                physics_changed = true;
+            } else {
+                speed = (speed / 2.0).max(0.25);
+            }
+        }
+        if is_key_pressed(KeyCode::Right) {
+            if shift {
+                config.gravity_angle = (config.gravity_angle + tilt_step).min(180.0);
+                physics_changed = true;
+            } else {
+                speed = (speed * 2.0).min(4.0);
+            }
+        }
+        // reverse the arrow of time with B
+        if is_key_pressed(KeyCode::B) {
+            time_dir = -time_dir;
+            retrace_time = 0.0;
+            retraced = true;
+        }
+
+        // K kicks the highlighted pendulum, or the first, to show how quickly
+        // it leaves the rest of the fan behind
+        if is_key_pressed(KeyCode::K) {
+            let i = hero_index(hero, dp_vec.len());
+            let nudge = config.nudge_radians();
+            dp_vec[i].angular1 += macroquad::rand::gen_range(-nudge, nudge);
+            dp_vec[i].angular2 += macroquad::rand::gen_range(-nudge, nudge);
+            // the kick adds energy and separates the pendulums, like changing the physics does
+            physics_changed = true;
+        }
+        // F freezes the highlighted pendulum, or the first, in place to show
+        // how far the rest of the fan moves on without it, and lets it go again
+        if is_key_pressed(KeyCode::F) {
+            let i = hero_index(hero, dp_vec.len());
+            dp_vec[i].frozen = !dp_vec[i].frozen;
+        }
+        // split each physics step into more or fewer integration steps with ' and ;
+        if is_key_pressed(KeyCode::Apostrophe) {
+            config.substeps = (config.substeps * 2).min(MAX_SUBSTEPS);
+            physics_changed = true;
+        }
+        if is_key_pressed(KeyCode::Semicolon) {
+            config.substeps = (config.substeps / 2).max(1);
+            physics_changed = true;
+        }
+        // change the gravity of every pendulum with the arrow keys
+        let gravity_step = 0.5;
+        if is_key_pressed(KeyCode::Up) || is_key_pressed(KeyCode::Down) {
+            if is_key_pressed(KeyCode::Up) {
+                config.gravity += gravity_step;
+            } else {
+                config.gravity = (config.gravity - gravity_step).max(0.0);
+            }
+            physics_changed = true;
+        }
+        if physics_changed {
+            for dp in dp_vec.iter_mut() {
+                dp.gravity = config.gravity;
+                dp.gravity_angle = config.gravity_tilt();
+                dp.damping = config.damping;
+                dp.drag = config.drag;
+                dp.drag_coeff = config.drag_coeff;
+                dp.substeps = config.substeps;
+                dp.max_step = config.max_step;
+            }
+            // changing gravity changes the potential energy, which isn't drift
+            initial_energy = dp_vec[0].total_energy();
+            initial_momentum = dp_vec[0].angular_momentum();
+            // and the pendulums diverge at a different rate
+            lyapunov = fan_lyapunov(&dp_vec, sim_time);
+            // and no longer follow the path they took to get here
+            forward_path.clear();
+        }
+        // R restarts everything, while N only starts the pendulums again from
+        // new random angles, keeping the view and settings
+        if is_key_pressed(KeyCode::N) {
+            angle1 = config.angle1;
+            angle2 = jittered_angle2(&config);
+            fan_seed = macroquad::rand::gen_range(0, u64::MAX);
+            rebuild = true;
+        }
+
+        // add or remove pendulums with the plus and minus keys
+        let count_step = 10;
+        if is_key_pressed(KeyCode::Equal) || is_key_pressed(KeyCode::KpAdd) {
+            config.dp_count = (config.dp_count + count_step).min(MAX_COUNT);
+            count_changed = true;
+        }
+        if is_key_pressed(KeyCode::Minus) || is_key_pressed(KeyCode::KpSubtract) {
+            config.dp_count = config.dp_count.saturating_sub(count_step).max(1);
+            count_changed = true;
+        }
+        if count_changed && !rebuild {
+            config.limit();
+            resize_fan(&mut dp_vec, &config, angle1, angle2);
+            for dp in dp_vec.iter_mut() {
+                dp.set_max_trace(config.max_trace);
+            }
+            // the whole fan is recolored so the colormap still sweeps from end to end
+            colors = render::fan_colors(dp_vec.len(), config.colormap, render::fan_alpha(config.dark_mode));
+            if dp_vec.len() < 2 {
+                lyapunov = None;
+            } else if lyapunov.is_none() {
+                lyapunov = fan_lyapunov(&dp_vec, sim_time);
+            }
+        }
+
+        if rebuild {
+            dp_vec = seeded_fan(&config, angle1, angle2, fan_seed);
+            colors = render::fan_colors(dp_vec.len(), config.colormap, render::fan_alpha(config.dark_mode));
+            initial_energy = dp_vec[0].total_energy();
+            initial_momentum = dp_vec[0].angular_momentum();
+            lyapunov = fan_lyapunov(&dp_vec, sim_time);
+            forward_path.clear();
+        }
+
+        // print what's needed to start the fan again from where it started with F2
+        if is_key_pressed(KeyCode::F2) {
+            print!("{}", reproduction(&config, angle1, angle2, fan_seed));
+        }
+
+        // save the whole fan with F5, and carry on from the save with F9
+        if is_key_pressed(KeyCode::F5) {
+            let state = SavedState { config: config.clone(), angle1, angle2, sim_time, pendulums: dp_vec.clone() };
+            match save_state("state.json", &state) {
+                Ok(()) => println!("Saved {} pendulums to state.json", dp_vec.len()),
+                Err(e) => eprintln!("Failed to save state.json: {}", e),
+            }
+        }
+        if is_key_pressed(KeyCode::F9) {
+            match load_state("state.json") {
+                Ok(state) if !state.pendulums.is_empty() => {
+                    config = state.config;
+                    angle1 = state.angle1;
+                    angle2 = state.angle2;
+                    sim_time = state.sim_time;
+                    dp_vec = state.pendulums;
+                    colors = render::fan_colors(dp_vec.len(), config.colormap, render::fan_alpha(config.dark_mode));
+                    initial_energy = dp_vec[0].total_energy();
+                    initial_momentum = dp_vec[0].angular_momentum();
+                    lyapunov = fan_lyapunov(&dp_vec, sim_time);
+                    forward_path.clear();
+                    dragging = None;
+                    println!("Loaded {} pendulums from state.json", dp_vec.len());
+                }
+                Ok(_) => eprintln!("Failed to load state.json: it has no pendulums"),
+                Err(e) => eprintln!("Failed to load state.json: {}", e),
+            }
+        }
+
+        let energy = dp_vec[0].total_energy();
+        let drift = if initial_energy == 0.0 { 0.0 } else { (energy - initial_energy) / initial_energy.abs() };
+        let drift_color = if drift.abs() > 0.01 { RED } else { text_color };
+
         fps_counter.update();
-        draw_text(&format!("R to restart"), 10.0, 20.0, 20.0, BLACK);
-        draw_text(&format!("SHIFT to show pendulum / CTRL to hide traces"), 10.0, 40.0, 20.0, BLACK);
-        draw_text(&format!("FPS: {}", fps_counter.fps()), 10.0, 60.0, 20.0, BLACK);
-        draw_text(&format!("Frame: {}", fps_counter.frame()), 10.0, 80.0, 20.0, BLACK);
+        // the lines of the hud, from the top down, in the color they're drawn in
+        let mut hud: Vec<(String, Color)> = Vec::new();
+        hud.push((String::from("R to restart / N for new starting angles / SPACE to pause / PERIOD to step"), text_color));
+        hud.push((String::from("SHIFT to show pendulum / CTRL to hide traces / P to screenshot"), text_color));
+        let highlighted = match hero {
+            Some(i) if i < dp_vec.len() => format!("pendulum {}{}", i, if dp_vec[i].frozen { ", frozen" } else { "" }),
+            _ => String::from("none"),
+        };
+        hud.push((format!("TAB to highlight a pendulum, highlighting {} / O to save its trace / K to nudge it / F to freeze it", highlighted), text_color));
+        hud.push((String::from("D for dark mode / F5 to save / F9 to load"), text_color));
+        hud.push((String::from("F1 to show the control panel / F2 to print the settings / F11 for fullscreen"), text_color));
+        let elapsed = steps_counted_from.0.elapsed().as_secs_f64();
+        if elapsed >= 1.0 {
+            steps_per_second = (steps - steps_counted_from.1) as f64 / elapsed;
+            steps_counted_from = (std::time::Instant::now(), steps);
+        }
+        hud.push((format!(
+            "FPS: {} / Physics: {:.0} steps/s / Energy: {:.4}", fps_counter.fps(), steps_per_second, energy,
+        ), text_color));
+        hud.push((format!(
+            "Frame: {} / {:.1}ms avg, {:.1} min, {:.1} max, {:.1} 99th",
+            fps_counter.frame(), fps_counter.avg_ms(), fps_counter.min_ms(), fps_counter.max_ms(), fps_counter.percentile(99.0),
+        ), text_color));
+        hud.push((format!("Time: {:.2}s simulated in {} steps", sim_time, steps), text_color));
+        hud.push((format!("Gravity: {:.2} at {:.0} degrees (UP / DOWN to change, SHIFT + LEFT / RIGHT to tilt)", config.gravity, config.gravity_angle), text_color));
+        hud.push((format!(
+            "Substeps: {}, {:.3}ms each (; / ' to change)", config.substeps, 1000.0 * physics_dt / config.substeps.max(1) as f64,
+        ), text_color));
+        hud.push((format!("Energy drift: {:.4}%", drift * 100.0), drift_color));
+        hud.push((format!(
+            "Angular momentum: {:.4}, changed by {:.2e}", dp_vec[0].angular_momentum(), dp_vec[0].angular_momentum() - initial_momentum,
+        ), text_color));
+        if config.escape_speed.is_some() {
+            let escaped = dp_vec.iter().filter(|dp| dp.escape_time > config.escape_after).count();
+            hud.push((
+                format!("Runaway pendulums: {} ({:?})", escaped, config.escape_action),
+                if escaped > 0 { RED } else { text_color },
+            ));
+        }
+        // every pendulum starts with the same energy, so their drifts can be compared
+        if config.compare_integrators {
+            for (dp, color) in dp_vec.iter().zip(colors.iter()) {
+                let drift = if initial_energy == 0.0 { 0.0 } else { (dp.total_energy() - initial_energy) / initial_energy.abs() };
+                hud.push((format!("{:?}: energy drift {:.4}%", dp.integrator, drift * 100.0), Color { a: 1.0, ..*color }));
+            }
+        }
+        let direction = if time_dir > 0.0 { "forwards" } else { "backwards" };
+        let slow = if is_key_down(KeyCode::LeftAlt) || is_key_down(KeyCode::RightAlt) { ", slowed" } else { "" };
+        hud.push((format!("Speed: {}x {}{} (LEFT / RIGHT to change, B to reverse, hold ALT to slow)", speed, direction, slow), text_color));
+        hud.push((format!("Traces: {}, length {} (T to toggle, [ / ] to change, BACKSPACE to clear)", if show_trace { "on" } else { "off" }, config.max_trace), text_color));
+        hud.push((format!("I to trace the inner bob / S to color traces by speed / C for {:?}", config.colormap), text_color));
+        hud.push((String::from("X to color by distance from the first pendulum / Z for the starting pose / L for the reach / A for a long exposure"), text_color));
+        hud.push((String::from("SCROLL to zoom / MIDDLE MOUSE to pan / HOME to reset the view"), text_color));
+        hud.push((String::from("V to change the view / M for the poincare section / H for energies / J for a phase inset"), text_color));
+        let exponent = match lyapunov.as_ref().and_then(|l| l.estimate()) {
+            Some(exponent) => format!("{:.3} /s", exponent),
+            None => String::from("-"),
+        };
+        hud.push((format!("Pendulums: {} (+ / - to change) / Lyapunov exponent: {}", dp_vec.len(), exponent), text_color));
+        if time_dir < 0.0 {
+            let status = if retraced { "so far" } else { "before straying" };
+            hud.push((format!("Retraced the forward path for {:.2}s {}", retrace_time, status), text_color));
+        }
+        if paused {
+            hud.push((format!("PAUSED at step {} (drag the bobs to move them)", steps), text_color));
+        }
+        if show_fps_graph {
+            render::draw_fps_graph(&fps_counter, text_color);
+        }
+        if show_histogram {
+            render::draw_energy_histogram(&dp_vec, text_color);
+        }
         
-        if !is_key_down(KeyCode::LeftControl) {
-            for i in 0..dp_vec.len() {
-                dp_vec[i].draw_trace();
+        // the separations change every step, so these colors are worked out every frame
+        if divergence_colors {
+            colors = render::divergence_colors(&dp_vec, render::fan_alpha(config.dark_mode));
+        }
+
+        // the other views replace the pendulums, which would cover them
+        match view {
+            View::Phase => render::draw_phase(&dp_vec, &colors),
+            View::Poincare => render::draw_poincare(&dp_vec, &colors),
+            View::Pendulum => {
+                let style = render::Style {
+                    rod: config.rod_thickness,
+                    node: config.node_radius,
+                    trace: config.trace_thickness,
+                    decay: config.trace_decay.map(|decay| decay as f32).unwrap_or_else(|| render::tail_decay(config.max_trace)),
+                    stride: config.trace_stride,
+                    fade: config.trace_fade,
+                    pretty: config.pretty,
+                };
+                // under everything else, like the background
+                if let Some(exposure) = exposure.as_mut() {
+                    exposure.add(&dp_vec, &colors, &camera, config.trace_thickness, trace_inner);
+                    exposure.draw();
+                }
+                render::draw_gravity_arrow(config.gravity_tilt(), text_color);
+                if show_reach {
+                    render::draw_reach(&dp_vec[0], text_color, &camera);
+                }
+                if show_ghost {
+                    let (theta1, theta2) = config::radians((angle1, angle2));
+                    let ghost = DoublePendulum::new(config.length1, config.length2, config.mass1, config.mass2, theta1, theta2);
+                    render::draw_ghost(&ghost, text_color, &camera, &style);
+                }
+                // the fan may have shrunk since the hero was picked
+                let hero = hero.filter(|&i| i < dp_vec.len());
+                if show_trace && !is_key_down(KeyCode::LeftControl) {
+                    let start = std::time::Instant::now();
+                    render::draw_traces(&dp_vec, &colors, &camera, &style, hero, trace_inner, speed_colors);
+                    trace_time += start.elapsed();
+                }
+                if is_key_down(KeyCode::LeftShift) {
+                    for (dp, color) in dp_vec.iter().zip(colors.iter()) {
+                        render::draw(dp, *color, &camera, &style);
+                    }  
+                } else if paused {
+                    // so there is something to drag
+                    render::draw(&dp_vec[0], colors[0], &camera, &style);
+                }
+                if let Some(i) = hero {
+                    render::draw_hero(&dp_vec[i], colors[i], &camera, &style);
+                }
+                if show_phase_inset {
+                    let i = hero.unwrap_or(0);
+                    render::draw_phase_inset(&dp_vec[i], colors[i], text_color, background);
+                }
+            }
+        }
+        // over the pendulums, so they can't hide it
+        render::draw_hud(&hud, background);
+
+        // a recording steps by the same amount every frame, so it comes out
+        // the same however fast the frames are drawn
+        let frame_time = match record_frames {
+            Some(_) => config.record_dt,
+            None => macroquad::time::get_frame_time() as f64,
+        };
+        // holding ALT slows time down for a closer look, without changing the step size
+        let slow_motion = is_key_down(KeyCode::LeftAlt) || is_key_down(KeyCode::RightAlt);
+        let rate = if slow_motion { speed * SLOW_MOTION } else { speed };
+        if !paused {
+            accumulator += rate * frame_time;
+        }
+        // a faster simulation needs more steps per frame, while a recording
+        // takes all of them, as its frames don't take real time to catch up with
+        let max_frame_substeps = match record_frames {
+            Some(_) => None,
+            None => Some((max_substeps as f64 * speed.max(1.0)) as usize),
+        };
+        let mut substeps = take_steps(&mut accumulator, physics_dt, max_frame_substeps);
+        // while paused, step forward once every time the period key is pressed
+        if paused && is_key_pressed(KeyCode::Period) {
+            substeps += 1;
+        }
+
+        for _ in 0..substeps {
+            if time_dir > 0.0 {
+                forward_path.push_back(dp_vec[0].state());
+                if forward_path.len() > MAX_FORWARD_PATH {
+                    forward_path.pop_front();
+                }
+            }
+            match replay {
+                Some(replay) => {
+                    let t = replay.time_at(sim_time + physics_dt * time_dir);
+                    dp_vec[0].seek(t, replay.state_at(t));
+                    update_all(&mut dp_vec[1..], physics_dt * time_dir);
+                }
+                None => update_all(&mut dp_vec, physics_dt * time_dir),
+            }
+            // stepping backwards should land on the state before the last forward step
+            // chaos and rounding errors mean it eventually won't
+            if time_dir < 0.0 {
+                match forward_path.pop_back() {
+                    Some(state) if retraced => {
+                        if lyapunov::state_distance(state, dp_vec[0].state()) < RETRACE_TOLERANCE {
+                            retrace_time += physics_dt;
+                        } else {
+                            retraced = false;
+                        }
+                    }
+                    Some(_) => {}
+                    // back before the oldest remembered state
+                    None => retraced = false,
+                }
+            }
+            steps += 1;
+            sim_time += physics_dt * time_dir;
+            if config.record {
+                trajectory.push(dp_vec[0].record_row(sim_time));
             }
         }
-        if is_key_down(KeyCode::LeftShift) {
-            for i in 0..dp_vec.len() {
-                dp_vec[i].draw();
-            }  
+        if let Some(escape_speed) = config.escape_speed_radians() {
+            let elapsed = substeps as f64 * physics_dt;
+            for (i, dp) in dp_vec.iter_mut().enumerate() {
+                if dp.angular1.abs().max(dp.angular2.abs()) > escape_speed {
+                    dp.escape_time += elapsed;
+                } else {
+                    dp.escape_time = 0.0;
+                }
+                if dp.escape_time <= config.escape_after || dp.diverged {
+                    continue;
+                }
+                match config.escape_action {
+                    config::EscapeAction::Flag => {}
+                    config::EscapeAction::Reset => {
+                        let (theta1, theta2) = config::radians((angle1, angle2));
+                        let (theta1, theta2) = offset_angles(&config, theta1, theta2, i);
+                        let (angular1, angular2) = config.start_angular_velocities();
+                        *dp = joining_pendulum(&config, theta1, theta2, angular1, angular2, dp.time);
+                    }
+                    config::EscapeAction::Freeze => dp.diverged = true,
+                }
+            }
         }
-        for i in 0..dp_vec.len() {
-            dp_vec[i].update(macroquad::time::get_frame_time() as f64);
+
+        // the fit only makes sense with time moving forwards
+        if substeps > 0 && time_dir > 0.0 {
+            if let Some(lyapunov) = lyapunov.as_mut() {
+                lyapunov.record(sim_time, &dp_vec[0], &dp_vec[1]);
+            }
         }
 
+        // write the recorded trajectory when E is pressed, or before quitting
+        if config.record && (is_key_pressed(KeyCode::E) || is_quit_requested()) {
+            match save_trajectory("trajectory.csv", &trajectory) {
+                Ok(()) => println!("Saved {} rows to trajectory.csv", trajectory.len()),
+                Err(e) => eprintln!("Failed to save trajectory.csv: {}", e),
+            }
+            if is_quit_requested() {
+                std::process::exit(0);
+            }
+        }
+
+        egui_macroquad::draw();
+
+        // the time drawing the traces only counts building them, the gpu
+        // draws them once the frame is finished, which the frame rate includes
+        if let Some(frames) = config.bench_frames {
+            benched_frames += 1;
+            if benched_frames >= frames {
+                let elapsed = bench_start.elapsed().as_secs_f64();
+                println!(
+                    "{} frames of {} pendulums in {:.3}s, {:.1} fps, {:.3}ms per frame drawing the traces",
+                    benched_frames, dp_vec.len(), elapsed, benched_frames as f64 / elapsed,
+                    trace_time.as_secs_f64() * 1000.0 / benched_frames as f64,
+                );
+                std::process::exit(0);
+            }
+        }
+
+        if let Some(frames) = record_frames {
+            if recorded_frames >= frames {
+                println!("Saved {} frames to frames/", recorded_frames);
+                std::process::exit(0);
+            }
+            let path = format!("frames/frame_{:05}.png", recorded_frames);
+            match save_screenshot(&path) {
+                Ok(()) => recorded_frames += 1,
+                Err(e) => {
+                    eprintln!("Failed to save {}, stopping the recording: {}", path, e);
+                    record_frames = None;
+                }
+            }
+        }
+
+        // save the trace of the highlighted pendulum, or the first, as an svg with O
+        if is_key_pressed(KeyCode::O) {
+            let i = hero_index(hero, dp_vec.len());
+            let path = format!("trace_{}.svg", unix_millis());
+            match std::fs::write(&path, render::trace_svg(&dp_vec[i], colors[i])) {
+                Ok(()) => println!("Saved the trace of pendulum {} to {}", i, path),
+                Err(e) => eprintln!("Failed to save the trace to {}: {}", path, e),
+            }
+        }
+
+        // take a screenshot once everything has been drawn
+        if is_key_pressed(KeyCode::P) {
+            let path = format!("capture_{}.png", unix_millis());
+            match save_screenshot(&path) {
+                Ok(()) => println!("Saved screenshot to {}", path),
+                Err(e) => eprintln!("Failed to save screenshot to {}: {}", path, e),
+            }
+        }
+
+        // with a lower max_fps, the frames are spaced out to save drawing, and
+        // the physics steps more times each frame to keep up with real time
+        if let Some(max_fps) = config.max_fps.filter(|&fps| fps > 0.0) {
+            let frame = std::time::Duration::from_secs_f64(1.0 / max_fps);
+            if let Some(wait) = frame.checked_sub(last_frame.elapsed()) {
+                std::thread::sleep(wait);
+            }
+        }
+        last_frame = std::time::Instant::now();
+
         next_frame().await
     }
 }
 
 
-#[macroquad::main("Double Pendulum")]
-async fn main() {
+// saves the current contents of the screen as a png
+fn save_screenshot(path: &str) -> image::ImageResult<()> {
+    let screen = get_screen_data();
+    let (width, height) = (screen.width as usize, screen.height as usize);
+
+    // the screen data is upside down, and the alpha is whatever the blending
+    // left behind, so flip it and make it opaque
+    let mut bytes = Vec::with_capacity(screen.bytes.len());
+    for row in screen.bytes.chunks_exact(width * 4).rev() {
+        bytes.extend_from_slice(row);
+    }
+    for pixel in bytes.chunks_exact_mut(4) {
+        pixel[3] = 255;
+    }
+
+    image::save_buffer(path, &bytes, width as u32, height as u32, image::ColorType::Rgba8)
+}
+
+// the header and rows of a recorded trajectory in csv format
+fn trajectory_csv(rows: &[(f64, f64, f64, f64, f64)]) -> String {
+    let mut csv = String::from("time,theta1,theta2,angular1,angular2\n");
+    for (t, theta1, theta2, angular1, angular2) in rows {
+        csv.push_str(&format!("{},{},{},{},{}\n", t, theta1, theta2, angular1, angular2));
+    }
+    csv
+}
+
+fn save_trajectory(path: &str, rows: &[(f64, f64, f64, f64, f64)]) -> std::io::Result<()> {
+    std::fs::write(path, trajectory_csv(rows))
+}
+
+// the config that starts a fan with these starting angles and seed again,
+// with every setting in it, as most of them can be changed while running
+// and only some have a command line flag
+// the jitter is turned off since the angles already include it
+fn reproduction(config: &config::Config, angle1: f64, angle2: f64, seed: u64) -> String {
+    let config = config::Config { angle1, angle2, angle2_jitter: 0.0, seed: Some(seed), ..config.clone() };
+    match toml::to_string(&config) {
+        Ok(toml) => format!("# save this as config.toml to start this fan again\n{}", toml),
+        Err(e) => format!("# failed to write the config: {}\n", e),
+    }
+}
+
+// everything needed to carry on a run from where it was saved
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SavedState {
+    config: config::Config,
+    // the starting angles of the fan, in degrees
+    angle1: f64,
+    angle2: f64,
+    sim_time: f64,
+    pendulums: Vec<DoublePendulum>,
+}
+
+fn save_state(path: &str, state: &SavedState) -> std::io::Result<()> {
+    std::fs::write(path, serde_json::to_string_pretty(state)?)
+}
+
+// the traces aren't saved, so each one is given back its room to grow into,
+// or the first positions added to them would reallocate
+fn load_state(path: &str) -> std::io::Result<SavedState> {
+    let mut state: SavedState = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+    for dp in state.pendulums.iter_mut() {
+        dp.set_max_trace(state.config.max_trace);
+    }
+    Ok(state)
+}
+
+// the time since the unix epoch in milliseconds
+fn unix_millis() -> u128 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis())
+        .unwrap_or(0)
+}
+
+
+// a seed that is different every time it is called
+fn clock_seed() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_nanos() as u64)
+        .unwrap_or(0)
+}
+
+
+// the single pendulum that runs without a window, starting from the angles
+// in the config, it has no trace as nothing is drawn
+fn headless_pendulum(config: &config::Config) -> DoublePendulum {
+    let (theta1, theta2) = config.start_angles();
+    let (angular1, angular2) = config.start_angular_velocities();
+    DoublePendulum::builder()
+        .length(config.length1, config.length2)
+        .mass(config.mass1, config.mass2)
+        .angles(theta1, theta2)
+        .angular_velocities(angular1, angular2)
+        .max_trace(0)
+        .build()
+        .with_gravity(config.gravity)
+        .with_gravity_angle(config.gravity_tilt())
+        .with_damping(config.damping)
+        .with_drag(config.drag)
+        .with_drag_coeff(config.drag_coeff)
+        .with_substeps(config.substeps)
+        .with_max_step(config.max_step)
+        .with_integrator(config.integrator)
+        .with_tolerance(config.tolerance)
+        .with_drive(config.drive_amp, config.drive_freq)
+}
+
+// steps a single pendulum without drawing anything, and returns its state
+// after every step (theta1, theta2, angular1, angular2), beginning with the initial state
+fn simulate_headless(config: &config::Config, steps: usize, dt: f64) -> Vec<(f64, f64, f64, f64)> {
+    let mut dp = headless_pendulum(config);
+    let mut history = Vec::with_capacity(steps + 1);
+    history.push((dp.theta1, dp.theta2, dp.angular1, dp.angular2));
+    for _ in 0..steps {
+        dp.update(dt);
+        history.push((dp.theta1, dp.theta2, dp.angular1, dp.angular2));
+    }
+    history
+}
+
+// the furthest an arm should turn in a single integration step, in radians
+// RK4 is accurate well past this, but the whips of the outer bob are much
+// sharper than a steady turn at the same speed, so this leaves room for them
+const MAX_STEP_TURN: f64 = 0.2;
+
+// a rough check that the integration steps of dt are short enough for how
+// fast the pendulums can turn, so a start that will look wrong from the
+// first step says why, and how many substeps would fix it
+// the fastest an arm can turn is when all the energy it starts with, above
+// the lowest it could have, goes into whichever arm is easiest to turn
+fn stability_warning(config: &config::Config, dt: f64) -> Option<String> {
+    let dp = headless_pendulum(config);
+    let (m1, m2, l1, l2) = (dp.mass1, dp.mass2, dp.length1, dp.length2);
+    let lowest = -(m1 + m2) * dp.gravity.abs() * l1 - m2 * dp.gravity.abs() * l2;
+    let kinetic = (dp.total_energy() - lowest).max(0.0);
+    let inertia = (m2 * l2 * l2).min((m1 + m2) * l1 * l1);
+    let fastest = if inertia > 0.0 { (2.0 * kinetic / inertia).sqrt() } else { 0.0 }
+        .max(dp.angular1.abs())
+        .max(dp.angular2.abs());
+
+    // the same split into substeps as update
+    let substeps = dp.substeps_for(dt);
+    let turn = fastest * dt.abs() / substeps;
+    if turn.is_finite() && turn <= MAX_STEP_TURN {
+        return None;
+    }
+    Some(format!(
+        concat!(
+            "Warning: the pendulums can turn up to {:.0} degrees per second, which is {:.1} degrees per integration step, ",
+            "more than the {:.1} that stays accurate, try substeps = {} or a higher physics rate",
+        ),
+        fastest.to_degrees(), turn.to_degrees(), MAX_STEP_TURN.to_degrees(), (fastest * dt.abs() / MAX_STEP_TURN).ceil(),
+    ))
+}
+
+// times how long a single pendulum takes to take steps steps of dt, so
+// changes to the physics can be compared, and returns it with the final state
+// the same config always takes the same steps, so only the time should change
+fn bench_physics(config: &config::Config, steps: usize, dt: f64) -> (std::time::Duration, (f64, f64, f64, f64)) {
+    let mut dp = headless_pendulum(config);
+    let start = std::time::Instant::now();
+    for _ in 0..steps {
+        dp.update(std::hint::black_box(dt));
+    }
+    (start.elapsed(), std::hint::black_box(dp.state()))
+}
+
+// times steps steps of dt of a whole fan, once serially and, with the
+// parallel feature, once on every core, the pendulums don't affect each
+// other so both have to end in exactly the same states
+fn bench_fan(config: &config::Config, steps: usize, dt: f64) -> Vec<(&'static str, std::time::Duration)> {
+    let (theta1, theta2) = config.start_angles();
+    let (angular1, angular2) = config.start_angular_velocities();
+    let fan: Vec<DoublePendulum> = fan_angles(config, theta1, theta2)
+        .into_iter()
+        .map(|(theta1, theta2)| fan_pendulum(config, theta1, theta2, angular1, angular2))
+        .collect();
+
+    let time = |update: fn(&mut [DoublePendulum], f64)| {
+        let mut dp_vec = fan.clone();
+        let start = std::time::Instant::now();
+        for _ in 0..steps {
+            update(&mut dp_vec, std::hint::black_box(dt));
+        }
+        (start.elapsed(), state_bits(&dp_vec))
+    };
+
+    let (serial, serial_states) = time(update_serial);
+    #[allow(unused_mut)]
+    let mut times = vec![("serially", serial)];
+    #[cfg(feature = "parallel")]
+    {
+        let (parallel, parallel_states) = time(update_parallel);
+        assert!(serial_states == parallel_states, "the parallel fan ended in different states to the serial one");
+        times.push(("in parallel", parallel));
+    }
+    std::hint::black_box(serial_states);
+    times
+}
+
+// the exact bits of the state of every pendulum, for checking that two ways
+// of stepping a fan give the same result, not just a close one
+fn state_bits(dp_vec: &[DoublePendulum]) -> Vec<[u64; 4]> {
+    dp_vec.iter()
+        .map(|dp| [dp.theta1.to_bits(), dp.theta2.to_bits(), dp.angular1.to_bits(), dp.angular2.to_bits()])
+        .collect()
+}
+
+
+fn main() {
+    let args = config::Args::parse();
+    let mut config = config::load_config("config.toml");
+    args.apply(&mut config);
+    config.limit();
+    eprintln!("{:#?}", config);
+    let dt = if args.headless || args.bench_physics.is_some() || args.bench_fan.is_some() {
+        args.dt
+    } else {
+        1.0 / if config.physics_rate > 0.0 { config.physics_rate } else { 240.0 }
+    };
+    if let Some(warning) = stability_warning(&config, dt) {
+        eprintln!("{}", warning);
+    }
+
+    // a benchmark of the physics, build with --release for a meaningful result
+    if let Some(millions) = args.bench_physics {
+        let steps = (millions * 1e6) as usize;
+        let (elapsed, state) = bench_physics(&config, steps, args.dt);
+        println!(
+            "{} steps of {}s in {:.3}s, {:.0} steps/s, ending at {:?}",
+            steps, args.dt, elapsed.as_secs_f64(), steps as f64 / elapsed.as_secs_f64(), state,
+        );
+        return;
+    }
+
+    // the same for the fan, which is where the parallel feature helps
+    if let Some(steps) = args.bench_fan {
+        for (name, elapsed) in bench_fan(&config, steps, args.dt) {
+            println!(
+                "{} pendulums, {} steps of {}s {} in {:.3}s, {:.0} pendulum steps/s",
+                config.dp_count, steps, args.dt, name, elapsed.as_secs_f64(),
+                (config.dp_count * steps) as f64 / elapsed.as_secs_f64(),
+            );
+        }
+        return;
+    }
+
+    // without a window, the trajectory is written to stdout instead
+    if args.headless {
+        let rows: Vec<_> = simulate_headless(&config, args.steps, args.dt)
+            .into_iter()
+            .enumerate()
+            .map(|(i, (theta1, theta2, angular1, angular2))| (i as f64 * args.dt, theta1, theta2, angular1, angular2))
+            .collect();
+        print!("{}", trajectory_csv(&rows));
+        return;
+    }
+
+    let replay = args.replay.as_ref().map(|path| match replay::Replay::load(path) {
+        Ok(replay) => replay,
+        Err(e) => {
+            eprintln!("Failed to load the replay {}: {}", path, e);
+            std::process::exit(1);
+        }
+    });
+
+    macroquad::Window::from_config(window_conf(&config), windowed(config, replay));
+}
+
+// the window is set up before it opens, so it doesn't flash at a different size
+fn window_conf(config: &config::Config) -> Conf {
+    Conf {
+        window_title: String::from("Double Pendulum"),
+        window_width: config.window_width,
+        window_height: config.window_height,
+        fullscreen: config.fullscreen,
+        high_dpi: config.high_dpi,
+        sample_count: config.msaa,
+        ..Default::default()
+    }
+}
+
+async fn windowed(config: config::Config, replay: Option<replay::Replay>) {
+    let mut screen = Screen {
+        fullscreen: config.fullscreen,
+        windowed_size: (config.window_width as f32, config.window_height as f32),
+    };
     loop {
-        run().await;
+        // without a seed each restart is different, so print the seed that
+        // was used in case the run needs to be replayed
+        let seed = config.seed.unwrap_or_else(clock_seed);
+        eprintln!("Seed: {}", seed);
+        run(&config, seed, replay.as_ref(), &mut screen).await;
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the random number generator is shared by the whole program, so the
+    // tests that seed it take turns
+    static RAND: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    #[test]
+    fn the_same_seed_starts_from_the_same_angle() {
+        let _rand = RAND.lock().unwrap();
+        let config = config::Config::default();
+        let theta2 = |seed| {
+            macroquad::rand::srand(seed);
+            create_fan(&config, config.angle1, jittered_angle2(&config))[0].theta2
+        };
+        assert_eq!(theta2(7), theta2(7));
+        assert_ne!(theta2(7), theta2(8));
+    }
+
+    #[test]
+    fn a_trajectory_has_a_header_and_five_columns() {
+        let mut dp = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 2.0, 0.5);
+        let mut rows = Vec::new();
+        for i in 0..3 {
+            dp.update(1.0 / 240.0);
+            rows.push(dp.record_row(i as f64 / 240.0));
+        }
+        let csv = trajectory_csv(&rows);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "time,theta1,theta2,angular1,angular2");
+        assert_eq!(lines.len(), rows.len() + 1);
+        for line in &lines[1..] {
+            let columns: Vec<f64> = line.split(',').map(|column| column.parse().unwrap()).collect();
+            assert_eq!(columns.len(), 5);
+        }
+    }
+
+    // the parallel feature has to be on for this to run,
+    // cargo test --features parallel
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn the_parallel_fan_matches_the_serial_one_exactly() {
+        let config = config::Config { dp_count: 2000, ..config::Config::default() };
+        let fan = create_fan(&config, config.angle1, config.angle2);
+        let (mut serial, mut parallel) = (fan.clone(), fan);
+        for _ in 0..240 {
+            update_serial(&mut serial, 1.0 / 240.0);
+            update_parallel(&mut parallel, 1.0 / 240.0);
+        }
+        assert_eq!(state_bits(&serial), state_bits(&parallel));
+    }
+
+    #[test]
+    fn the_config_angles_are_in_degrees() {
+        let config: config::Config = toml::from_str("angle1 = 90.0").unwrap();
+        let dp_vec = create_fan(&config, config.angle1, config.angle2);
+        assert_eq!(dp_vec[0].theta1, std::f64::consts::FRAC_PI_2);
+        assert_eq!(dp_vec[0].theta2, config.angle2.to_radians());
+    }
+
+    // json has no infinity, so an uncapped step has to come back as None
+    #[test]
+    fn an_uncapped_max_step_is_saved_and_loaded() {
+        let config = config::Config { max_step: Some(f64::INFINITY), ..config::Config::default() };
+        let state = SavedState {
+            pendulums: create_fan(&config, config.angle1, config.angle2),
+            config,
+            angle1: 180.0,
+            angle2: 180.0,
+            sim_time: 0.0,
+        };
+        let path = std::env::temp_dir().join(format!("chaos_uncapped_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        save_state(path, &state).unwrap();
+        let loaded = load_state(path);
+        std::fs::remove_file(path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded.config.max_step, None);
+        assert_eq!(loaded.pendulums[0].max_step, None);
+        assert_eq!(loaded.pendulums[0].substeps_for(10.0), state.pendulums[0].substeps_for(10.0));
+    }
+
+    #[test]
+    fn recorded_frames_take_all_of_their_steps() {
+        let (physics_dt, record_dt, frames): (f64, f64, usize) = (1.0 / 240.0, 1.0 / 24.0, 100);
+        // the cap for frames of 1/30s, which a recording frame of 1/24s is longer than
+        let cap = Some((1.0 / 30.0 / physics_dt).ceil() as usize);
+        let (mut accumulator, mut capped) = (0.0, 0.0);
+        let (mut steps, mut capped_steps) = (0, 0);
+        for _ in 0..frames {
+            accumulator += record_dt;
+            steps += take_steps(&mut accumulator, physics_dt, None);
+            capped += record_dt;
+            capped_steps += take_steps(&mut capped, physics_dt, cap);
+        }
+        let advanced = steps as f64 * physics_dt + accumulator;
+        assert!((advanced - frames as f64 * record_dt).abs() < 1e-9);
+        assert!((steps as i64 - 10 * frames as i64).abs() <= 1);
+        // which the cap would have dropped some of
+        assert!(capped_steps < steps);
+    }
+
+    #[test]
+    fn the_printed_config_starts_the_same_random_fan() {
+        let _rand = RAND.lock().unwrap();
+        let config = config::Config { dp_count: 20, fan_mode: config::FanMode::Gaussian, spread: 5.0, ..config::Config::default() };
+        let fan = seeded_fan(&config, 170.0, 100.0, 42);
+        // as if K had nudged a pendulum since
+        macroquad::rand::gen_range(0.0, 1.0);
+
+        let printed: config::Config = toml::from_str(&reproduction(&config, 170.0, 100.0, 42)).unwrap();
+        let again = seeded_fan(&printed, printed.angle1, printed.angle2, printed.seed.unwrap());
+        assert_eq!(state_bits(&fan), state_bits(&again));
+    }
+
+    #[test]
+    fn a_loaded_fan_has_room_for_its_traces() {
+        let config = config::Config { dp_count: 3, max_trace: 50, ..config::Config::default() };
+        let state = SavedState {
+            pendulums: create_fan(&config, config.angle1, config.angle2),
+            config,
+            angle1: 180.0,
+            angle2: 180.0,
+            sim_time: 0.0,
+        };
+        let path = std::env::temp_dir().join(format!("chaos_traces_{}.json", std::process::id()));
+        let path = path.to_str().unwrap();
+        save_state(path, &state).unwrap();
+        let loaded = load_state(path);
+        std::fs::remove_file(path).unwrap();
+
+        for mut dp in loaded.unwrap().pendulums {
+            let capacity = dp.prev_angles.capacity();
+            assert!(capacity > 50);
+            for _ in 0..200 {
+                dp.update(1.0 / 240.0);
+                assert_eq!(dp.prev_angles.capacity(), capacity);
+            }
+            assert_eq!(dp.prev_angles.len(), 50);
+        }
+    }
+
+    #[test]
+    fn a_hero_past_the_end_of_the_fan_falls_back_to_the_first() {
+        assert_eq!(hero_index(Some(2), 3), 2);
+        assert_eq!(hero_index(Some(3), 3), 0);
+        assert_eq!(hero_index(None, 3), 0);
     }
 }
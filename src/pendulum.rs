@@ -0,0 +1,1157 @@
+// pendulum.rs
+
+// the physics of the double pendulum
+// nothing here depends on how, or if, the pendulum is drawn
+
+use std::collections::VecDeque;
+
+use glam::DVec4;
+use serde::{Deserialize, Serialize};
+
+
+// the default gravitational acceleration
+pub const GRAVITY: f64 = 9.81;
+
+
+// the most points kept in the poincare section of each pendulum, the oldest
+// are dropped after that so a long run doesn't keep growing
+pub const MAX_POINCARE: usize = 2000;
+
+// the smallest denominator in the equations of motion, as a fraction of the total mass
+const MIN_DENOMINATOR: f64 = 1e-4;
+
+
+// wraps an angle to [-pi, pi), the same position on the circle
+pub fn wrap_angle(angle: f64) -> f64 {
+    (angle + std::f64::consts::PI).rem_euclid(std::f64::consts::TAU) - std::f64::consts::PI
+}
+
+
+// how each arm is slowed down as it turns
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Drag {
+    // friction at the joints, damping * omega
+    #[default]
+    Linear,
+    // air resistance, drag_coeff * omega * |omega|, which matters more at high speeds
+    Quadratic,
+}
+
+
+// the method used to step the equations of motion forwards
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Integrator {
+    // first order, gains energy every step until it flies apart
+    Euler,
+    // first order too, but the angles move with the new velocities, so it
+    // tends to lose energy rather than gain it
+    SemiImplicitEuler,
+    // fourth order, and slowly loses energy
+    #[default]
+    Rk4,
+    // fifth order, with substeps as short as they need to be to keep the
+    // error of each one below the pendulum's tolerance
+    DormandPrince,
+}
+
+impl Integrator {
+    pub const ALL: [Integrator; 4] = [Integrator::Euler, Integrator::SemiImplicitEuler, Integrator::Rk4, Integrator::DormandPrince];
+}
+
+
+fn default_substeps() -> usize {
+    1
+}
+
+fn default_max_step() -> Option<f64> {
+    Some(0.02)
+}
+
+fn default_tolerance() -> f64 {
+    1e-9
+}
+
+
+// double pendulum state
+// theta1 and theta2 are the inner angles of the pendulum
+// the trace and poincare section aren't saved, they build up again as it moves
+#[derive(Serialize, Deserialize, Clone)]
+pub struct DoublePendulum {
+    pub length1: f64,
+    pub length2: f64,
+    pub theta1: f64,
+    pub theta2: f64,
+    pub mass1: f64,
+    pub mass2: f64,
+    pub gravity: f64,
+    // how far gravity is turned from straight down, in the direction the
+    // angles increase, like a pendulum swinging on a tilted table
+    #[serde(default)]
+    pub gravity_angle: f64,
+    pub damping: f64,
+    #[serde(default)]
+    pub drag: Drag,
+    // the strength of the quadratic drag, damping is only used by the linear
+    #[serde(default)]
+    pub drag_coeff: f64,
+    pub angular1: f64,
+    pub angular2: f64,
+    // the pivot moves up and down as drive_amp * sin(drive_freq * time)
+    pub drive_amp: f64,
+    pub drive_freq: f64,
+    // the simulated time, which sets the phase of the drive
+    pub time: f64,
+    // the least amount of integration steps each update is split into
+    #[serde(default = "default_substeps")]
+    pub substeps: usize,
+    // the longest single integration step, longer updates are split up
+    // the error of an RK4 step grows with the fifth power of its length, and
+    // the fast whips of the outer bob need steps well under their duration,
+    // at 0.02s a lagging frame stays accurate, and None never splits
+    // json has no infinity, so no cap has to be None rather than inf to be saved
+    #[serde(default = "default_max_step")]
+    pub max_step: Option<f64>,
+    #[serde(default)]
+    pub integrator: Integrator,
+    // the largest error allowed in a single substep of the dormand_prince integrator
+    #[serde(default = "default_tolerance")]
+    pub tolerance: f64,
+    // set once the state stops being finite, ex, from an absurd timestep
+    // the pendulum is then frozen at its last finite state
+    pub diverged: bool,
+    // held still on purpose, update leaves it and its trace as they are
+    // until it's unfrozen, while the rest of the fan carries on
+    #[serde(default)]
+    pub frozen: bool,
+    max_trace: usize,
+    // the trace is sampled every trace_interval of simulated time, however
+    // often update is called, trace_elapsed is the time since the last sample
+    trace_interval: f64,
+    trace_elapsed: f64,
+    // the previous angles used to draw the trace, the front is the newest
+    // along with the angular velocities and the speed of the outer bob at the time
+    #[serde(skip)]
+    pub prev_angles: VecDeque<(f64, f64, f64, f64, f64)>,
+    // the poincare section, (theta2, angular2) every time theta1 crosses
+    // zero while swinging in the positive direction, the newest at the back
+    #[serde(skip)]
+    pub poincare: VecDeque<(f64, f64)>,
+    // how long the pendulum has been spinning faster than the escape speed
+    // of the program running it, which decides what to do about it
+    #[serde(skip)]
+    pub escape_time: f64,
+}
+
+impl DoublePendulum {
+    pub fn new(length1: f64, length2: f64, mass1: f64, mass2: f64, theta1: f64, theta2: f64) -> DoublePendulum {
+        DoublePendulum { length1, length2, theta1, theta2, mass1, mass2, gravity: GRAVITY, gravity_angle: 0.0, damping: 0.0, drag: Drag::Linear, drag_coeff: 0.0, angular1: 0.0, angular2: 0.0, drive_amp: 0.0, drive_freq: 0.0, time: 0.0, substeps: 1, max_step: Some(0.02), integrator: Integrator::Rk4, tolerance: 1e-9, diverged: false, frozen: false, max_trace: 150, trace_interval: 1.0 / 240.0, trace_elapsed: 0.0, prev_angles: VecDeque::with_capacity(150 + 1), poincare: VecDeque::new(), escape_time: 0.0 }
+    }
+
+    pub fn with_gravity(mut self, gravity: f64) -> DoublePendulum {
+        self.gravity = gravity;
+        self
+    }
+
+    // tilts gravity by angle radians, see gravity_angle
+    pub fn with_gravity_angle(mut self, angle: f64) -> DoublePendulum {
+        self.gravity_angle = angle;
+        self
+    }
+
+    // friction at the joints, proportional to the angular velocity
+    pub fn with_damping(mut self, damping: f64) -> DoublePendulum {
+        self.damping = damping;
+        self
+    }
+
+    // splits every update into at least this many steps, which is more
+    // accurate but slower
+    pub fn with_substeps(mut self, substeps: usize) -> DoublePendulum {
+        self.substeps = substeps.max(1);
+        self
+    }
+
+    // the longest single integration step, see max_step
+    pub fn with_max_step(mut self, max_step: Option<f64>) -> DoublePendulum {
+        self.max_step = max_step;
+        self
+    }
+
+    pub fn with_integrator(mut self, integrator: Integrator) -> DoublePendulum {
+        self.integrator = integrator;
+        self
+    }
+
+    // the error allowed in each substep, see tolerance
+    pub fn with_tolerance(mut self, tolerance: f64) -> DoublePendulum {
+        self.tolerance = tolerance;
+        self
+    }
+
+    // whether the arms are slowed by damping, or by drag_coeff
+    pub fn with_drag(mut self, drag: Drag) -> DoublePendulum {
+        self.drag = drag;
+        self
+    }
+
+    // air resistance, proportional to the square of the angular velocity
+    pub fn with_drag_coeff(mut self, drag_coeff: f64) -> DoublePendulum {
+        self.drag_coeff = drag_coeff;
+        self
+    }
+
+    // moves the pivot up and down, amp in units of length and freq in radians per second
+    pub fn with_drive(mut self, amp: f64, freq: f64) -> DoublePendulum {
+        self.drive_amp = amp;
+        self.drive_freq = freq;
+        self
+    }
+
+    // the amount of previous positions kept for the trace
+    pub fn with_max_trace(mut self, max_trace: usize) -> DoublePendulum {
+        self.set_max_trace(max_trace);
+        self
+    }
+
+    // the amount of positions added to the trace per second of simulated time
+    // a rate of 0 adds one every substep of an update
+    pub fn with_trace_rate(mut self, rate: f64) -> DoublePendulum {
+        self.trace_interval = if rate > 0.0 { 1.0 / rate } else { 0.0 };
+        self
+    }
+
+    // forgets the trace, which starts again from wherever the pendulum is
+    // the room for it is kept, so it doesn't have to grow back
+    pub fn clear_trace(&mut self) {
+        self.prev_angles.clear();
+    }
+
+    // removes the oldest positions if the trace is now too long
+    // the trace holds one extra position between adding the newest and
+    // dropping the oldest, and has room for exactly that many so it never
+    // reallocates while running, changing the length reallocates it once
+    pub fn set_max_trace(&mut self, max_trace: usize) {
+        self.max_trace = max_trace;
+        self.prev_angles.truncate(max_trace);
+        self.prev_angles.shrink_to(max_trace + 1);
+        self.prev_angles.reserve_exact((max_trace + 1).saturating_sub(self.prev_angles.len()));
+    }
+}
+
+
+// builds a pendulum one part at a time, anything not set keeps its default,
+// rods and bobs of 1, hanging straight down and still
+// ex, DoublePendulum::builder().angles(3.0, 2.0).mass(2.0, 1.0).build()
+pub struct DoublePendulumBuilder {
+    length: (f64, f64),
+    angles: (f64, f64),
+    angular_velocities: (f64, f64),
+    mass: (f64, f64),
+    max_trace: usize,
+}
+
+impl DoublePendulum {
+    pub fn builder() -> DoublePendulumBuilder {
+        DoublePendulumBuilder { length: (1.0, 1.0), angles: (0.0, 0.0), angular_velocities: (0.0, 0.0), mass: (1.0, 1.0), max_trace: 150 }
+    }
+}
+
+impl DoublePendulumBuilder {
+    // the lengths of the inner and outer rods
+    pub fn length(mut self, length1: f64, length2: f64) -> DoublePendulumBuilder {
+        self.length = (length1, length2);
+        self
+    }
+
+    // the starting angles, in radians
+    pub fn angles(mut self, theta1: f64, theta2: f64) -> DoublePendulumBuilder {
+        self.angles = (theta1, theta2);
+        self
+    }
+
+    // the starting angular velocities, in radians per second
+    pub fn angular_velocities(mut self, angular1: f64, angular2: f64) -> DoublePendulumBuilder {
+        self.angular_velocities = (angular1, angular2);
+        self
+    }
+
+    // the masses of the inner and outer bobs
+    pub fn mass(mut self, mass1: f64, mass2: f64) -> DoublePendulumBuilder {
+        self.mass = (mass1, mass2);
+        self
+    }
+
+    pub fn max_trace(mut self, max_trace: usize) -> DoublePendulumBuilder {
+        self.max_trace = max_trace;
+        self
+    }
+
+    pub fn build(self) -> DoublePendulum {
+        let mut dp = DoublePendulum::new(
+            self.length.0, self.length.1, self.mass.0, self.mass.1, self.angles.0, self.angles.1,
+        ).with_max_trace(self.max_trace);
+        dp.angular1 = self.angular_velocities.0;
+        dp.angular2 = self.angular_velocities.1;
+        dp
+    }
+}
+
+
+// the motion of the pendulum is calculated using the Runge-Kutta method (RK4)
+// https://en.wikipedia.org/wiki/Double_pendulum
+// https://en.wikipedia.org/wiki/Runge%E2%80%93Kutta_methods
+// https://www.diego.assencio.com/?index=1500c66ae7ab27bb0106467c68feebc6
+impl DoublePendulum {
+    // a negative timestep runs the pendulum backwards in time, which retraces
+    // its path as long as there's no damping, the trace keeps growing from
+    // the front either way so it always ends at the bob
+    pub fn update(&mut self, timestep: f64) {
+        if self.diverged || self.frozen {
+            return;
+        }
+        // dormand_prince picks its own substeps
+        if self.integrator == Integrator::DormandPrince {
+            self.update_adaptive(timestep, self.tolerance);
+            return;
+        }
+        let start_time = self.time;
+
+        let substeps = self.substeps_for(timestep);
+        let substep = timestep / substeps;
+
+        // the trace is sampled at the start of every substep rather than once
+        // per update, so a long update still leaves a smooth trace behind
+        let params = self.params();
+        let mut state = DVec4::new(self.theta1, self.theta2, self.angular1, self.angular2);
+        for _ in 0..(substeps as usize) {
+            if state.is_finite() {
+                self.sample_trace(state, substep);
+            }
+            let next = match self.integrator {
+                Integrator::Euler => params.euler_step(state, self.time, substep),
+                Integrator::SemiImplicitEuler => params.semi_implicit_euler_step(state, self.time, substep),
+                Integrator::Rk4 => step(state, self.time, substep, &params),
+                Integrator::DormandPrince => unreachable!("dormand-prince is stepped by update_adaptive"),
+            };
+            self.record_crossing(state, next);
+            state = next;
+            self.time += substep;
+        }
+
+        if !state.is_finite() {
+            self.time = start_time;
+            self.diverge();
+            return;
+        }
+        self.set_state(state);
+    }
+
+    // if the timestep is too large, a single RK4 step becomes inaccurate
+    // this can happen if the program lags, ex, the user moves the window
+    // so the timestep is split into equal substeps of at most max_step, or
+    // more if asked for, up to a limit, so an absurd timestep diverges
+    // instead of hanging
+    pub fn substeps_for(&self, timestep: f64) -> f64 {
+        let max_step = self.max_step.unwrap_or(f64::INFINITY);
+        (timestep.abs() / max_step).ceil().max(self.substeps as f64).clamp(1.0, 10000.0)
+    }
+
+    // moves the pendulum straight to a state at time, instead of simulating
+    // its way there, the trace and poincare section are added to as if it had
+    pub fn seek(&mut self, time: f64, state: (f64, f64, f64, f64)) {
+        let (prev, next) = (DVec4::new(self.theta1, self.theta2, self.angular1, self.angular2), DVec4::from(state));
+        self.sample_trace(prev, time - self.time);
+        self.record_crossing(prev, next);
+        self.time = time;
+        self.set_state(next);
+    }
+
+    // freezes the pendulum, which hasn't been given the non-finite state
+    fn diverge(&mut self) {
+        eprintln!(
+            "A pendulum diverged at t = {:.3}s from {:?}, it will stay where it is",
+            self.time, self.state(),
+        );
+        self.diverged = true;
+    }
+
+    // stores a new state, wrapping the angles so they don't grow without
+    // bound and lose precision as the pendulum swings over the top
+    // the trace and drawing only use the sin and cos of the angles, so
+    // wrapping doesn't move anything on the screen
+    fn set_state(&mut self, state: DVec4) {
+        self.theta1 = wrap_angle(state.x);
+        self.theta2 = wrap_angle(state.y);
+        self.angular1 = state.z;
+        self.angular2 = state.w;
+    }
+
+    // records a state if it's been long enough since the last sample
+    // at most one sample is taken per substep, however long the substep is
+    fn sample_trace(&mut self, state: DVec4, timestep: f64) {
+        self.trace_elapsed += timestep.abs();
+        // the small tolerance stops rounding from skipping a sample when the
+        // timestep divides the interval exactly
+        if self.trace_elapsed + 1e-9 >= self.trace_interval {
+            self.push_trace(state);
+            self.trace_elapsed = if self.trace_interval > 0.0 {
+                (self.trace_elapsed - self.trace_interval).max(0.0) % self.trace_interval
+            } else {
+                0.0
+            };
+        }
+    }
+
+    // records a state as the newest point of the trace
+    fn push_trace(&mut self, state: DVec4) {
+        let speed = self.speed_at(state.x, state.y, state.z, state.w);
+        self.prev_angles.push_front((state.x, state.y, state.z, state.w, speed));
+        if self.prev_angles.len() > self.max_trace {
+            self.prev_angles.pop_back();
+        }
+    }
+
+    // adds a point to the poincare section if theta1 crossed zero in the
+    // positive direction between two states
+    // the crossing is found by linearly interpolating between them
+    fn record_crossing(&mut self, prev: DVec4, next: DVec4) {
+        if prev.x < 0.0 && next.x >= 0.0 && next.z > 0.0 {
+            let t = -prev.x / (next.x - prev.x);
+            let crossing = prev.lerp(next, t);
+            if self.poincare.len() >= MAX_POINCARE {
+                self.poincare.pop_front();
+            }
+            self.poincare.push_back((crossing.y, crossing.w));
+        }
+    }
+
+    // advances the full timestep using the Dormand-Prince method (RK45)
+    // the timestep is split into as many substeps as needed to keep the
+    // estimated local error of each substep below tol, a negative timestep
+    // runs the pendulum backwards the same way
+    pub fn update_adaptive(&mut self, timestep: f64, tol: f64) {
+        // the substeps never get shorter than this, and are accepted
+        // regardless once they're this short, so the loop always ends
+        let min_step = 1e-9;
+
+        if self.diverged || self.frozen {
+            return;
+        }
+        let start_time = self.time;
+        let direction = timestep.signum();
+
+        let params = self.params();
+        let mut state = DVec4::new(self.theta1, self.theta2, self.angular1, self.angular2);
+        // both of these are lengths of time, the direction is added back when stepping
+        let mut remaining = timestep.abs();
+        let mut step = remaining;
+        while remaining > 0.0 {
+            step = step.min(remaining);
+            let (next, error) = params.dormand_prince_step(state, self.time, direction * step);
+            // the error of a state that isn't finite is nan, which would
+            // shrink the substep forever without getting anywhere
+            if !(next.is_finite() && error.is_finite()) {
+                self.time = start_time;
+                self.diverge();
+                return;
+            }
+            if error <= tol || step <= min_step {
+                self.sample_trace(state, step);
+                self.record_crossing(state, next);
+                state = next;
+                remaining -= step;
+                self.time += direction * step;
+            }
+
+            // grow or shrink the next substep based on how close the error was to tol
+            let factor = if error > 0.0 {
+                (0.9 * (tol / error).powf(0.2)).clamp(0.2, 5.0)
+            } else {
+                5.0
+            };
+            step = (step * factor).max(min_step);
+        }
+
+        self.set_state(state);
+    }
+}
+
+
+// everything about a pendulum that decides how it moves, apart from its state
+#[derive(Clone, Copy, Debug)]
+pub struct PendulumParams {
+    pub length1: f64,
+    pub length2: f64,
+    pub mass1: f64,
+    pub mass2: f64,
+    pub gravity: f64,
+    pub gravity_angle: f64,
+    pub damping: f64,
+    pub drag: Drag,
+    pub drag_coeff: f64,
+    pub drive_amp: f64,
+    pub drive_freq: f64,
+}
+
+impl DoublePendulum {
+    pub fn params(&self) -> PendulumParams {
+        PendulumParams {
+            length1: self.length1,
+            length2: self.length2,
+            mass1: self.mass1,
+            mass2: self.mass2,
+            gravity: self.gravity,
+            gravity_angle: self.gravity_angle,
+            damping: self.damping,
+            drag: self.drag,
+            drag_coeff: self.drag_coeff,
+            drive_amp: self.drive_amp,
+            drive_freq: self.drive_freq,
+        }
+    }
+}
+
+// a single RK4 step of dt from state (theta1, theta2, angular1, angular2) at
+// time t
+// t is the phase of a driven pivot, which moves as drive_amp * sin(drive_freq * t),
+// the stages of the step look at the pivot at t, t + dt / 2 and t + dt, so
+// the phase can't be fixed in params, and without a drive t has no effect
+// it doesn't touch a pendulum, so the numerics can be used and checked on
+// their own, update wraps it with the substeps, the trace and the guards
+pub fn step(state: DVec4, t: f64, dt: f64, params: &PendulumParams) -> DVec4 {
+    let k1 = params.derivative(t, state);
+    let k2 = params.derivative(t + dt / 2.0, state + (dt / 2.0) * k1);
+    let k3 = params.derivative(t + dt / 2.0, state + (dt / 2.0) * k2);
+    let k4 = params.derivative(t + dt, state + dt * k3);
+
+    state + (dt / 6.0) * (k1 + 2.0 * k2 + 2.0 * k3 + k4)
+}
+
+impl PendulumParams {
+    // moves along the derivative at the start of the step
+    fn euler_step(&self, current: DVec4, t: f64, timestep: f64) -> DVec4 {
+        current + timestep * self.derivative(t, current)
+    }
+
+    // updates the angular velocities first, then moves the angles with them
+    fn semi_implicit_euler_step(&self, current: DVec4, t: f64, timestep: f64) -> DVec4 {
+        let derivative = self.derivative(t, current);
+        let (angular1, angular2) = (current.z + timestep * derivative.z, current.w + timestep * derivative.w);
+        DVec4::new(current.x + timestep * angular1, current.y + timestep * angular2, angular1, angular2)
+    }
+
+    // a single Dormand-Prince step, returns the 5th order estimate along with
+    // the largest difference between it and the 4th order estimate
+    // https://en.wikipedia.org/wiki/Dormand%E2%80%93Prince_method
+    fn dormand_prince_step(&self, current: DVec4, t: f64, h: f64) -> (DVec4, f64) {
+        let k1 = self.derivative(t, current);
+        let k2 = self.derivative(t + h / 5.0, current + h * (1.0 / 5.0) * k1);
+        let k3 = self.derivative(t + h * 3.0 / 10.0, current + h * (
+            (3.0 / 40.0) * k1 + (9.0 / 40.0) * k2
+        ));
+        let k4 = self.derivative(t + h * 4.0 / 5.0, current + h * (
+            (44.0 / 45.0) * k1 - (56.0 / 15.0) * k2 + (32.0 / 9.0) * k3
+        ));
+        let k5 = self.derivative(t + h * 8.0 / 9.0, current + h * (
+            (19372.0 / 6561.0) * k1 - (25360.0 / 2187.0) * k2 
+            + (64448.0 / 6561.0) * k3 - (212.0 / 729.0) * k4
+        ));
+        let k6 = self.derivative(t + h, current + h * (
+            (9017.0 / 3168.0) * k1 - (355.0 / 33.0) * k2 + (46732.0 / 5247.0) * k3 
+            + (49.0 / 176.0) * k4 - (5103.0 / 18656.0) * k5
+        ));
+
+        let fifth = current + h * (
+            (35.0 / 384.0) * k1 + (500.0 / 1113.0) * k3 + (125.0 / 192.0) * k4 
+            - (2187.0 / 6784.0) * k5 + (11.0 / 84.0) * k6
+        );
+        let k7 = self.derivative(t + h, fifth);
+        let fourth = current + h * (
+            (5179.0 / 57600.0) * k1 + (7571.0 / 16695.0) * k3 + (393.0 / 640.0) * k4 
+            - (92097.0 / 339200.0) * k5 + (187.0 / 2100.0) * k6 + (1.0 / 40.0) * k7
+        );
+
+        (fifth, (fifth - fourth).abs().max_element())
+    }
+
+    // the general equations of motion, valid for any lengths and masses
+    // each angular acceleration is a numerator (f1, f2) divided by the
+    // rod length times a shared denominator
+    // this is the hottest part of the simulation, so both accelerations are
+    // found together and each sine and cosine is only worked out once
+    // gravity pulls along tilt, and only the terms with gravity in them see
+    // the tilt, as the angles between the rods are the same whichever way is down
+    fn accelerations(&self, (gravity, tilt): (f64, f64), theta1: f64, theta2: f64, angular1: f64, angular2: f64) -> (f64, f64) {
+        let (m1, m2) = (self.mass1, self.mass2);
+        let (l1, l2) = (self.length1, self.length2);
+        // the angle of the inner rod from the way gravity pulls
+        let (sin1, cos1) = (theta1 - tilt).sin_cos();
+        let (sin_delta, cos_delta) = (theta1 - theta2).sin_cos();
+        let (w1_sq, w2_sq) = (angular1 * angular1, angular2 * angular2);
+
+        // the denominator is 2 * m1 + 2 * m2 * sin(delta)^2, which is only zero if
+        // the inner bob has no mass, but gets small enough to blow up the
+        // simulation when it's nearly massless, so it's kept above a fraction
+        // of the total mass, well below anything a normal pendulum reaches
+        let denominator = (2.0 * m1 + m2 - m2 * (2.0 * theta1 - 2.0 * theta2).cos()).max(MIN_DENOMINATOR * (m1 + m2));
+        let f1 = -gravity * (2.0 * m1 + m2) * sin1
+            - m2 * gravity * (theta1 - 2.0 * theta2 + tilt).sin()
+            - 2.0 * sin_delta * m2 * (w2_sq * l2 + w1_sq * l1 * cos_delta);
+        let f2 = 2.0 * sin_delta * (
+            w1_sq * l1 * (m1 + m2)
+            + gravity * (m1 + m2) * cos1
+            + w2_sq * l2 * m2 * cos_delta
+        );
+
+        (
+            f1 / (l1 * denominator) - self.drag_force(angular1),
+            f2 / (l2 * denominator) - self.drag_force(angular2),
+        )
+    }
+
+    // the slowing of an arm turning at angular, which is nothing without
+    // damping, or drag_coeff for quadratic drag
+    fn drag_force(&self, angular: f64) -> f64 {
+        match self.drag {
+            Drag::Linear => self.damping * angular,
+            Drag::Quadratic => self.drag_coeff * angular * angular.abs(),
+        }
+    }
+
+    // in the frame of a moving pivot, its acceleration is felt as an extra
+    // force on both bobs, so a driven pivot is the same as a changing gravity
+    // down is positive, so the pivot accelerating down weakens gravity
+    // returns the strength of gravity and the angle it pulls at
+    fn effective_gravity(&self, t: f64) -> (f64, f64) {
+        if self.drive_amp == 0.0 {
+            return (self.gravity, self.gravity_angle);
+        }
+        let pivot_accel = -self.drive_amp * self.drive_freq * self.drive_freq * (self.drive_freq * t).sin();
+        if self.gravity_angle == 0.0 {
+            return (self.gravity - pivot_accel, 0.0);
+        }
+        // the pivot only moves up and down, so a tilted gravity and the
+        // drive add up to a field pointing somewhere in between
+        let x = self.gravity * self.gravity_angle.sin();
+        let y = self.gravity * self.gravity_angle.cos() - pivot_accel;
+        (x.hypot(y), x.atan2(y))
+    }
+
+    // how the state (theta1, theta2, angular1, angular2) is changing at time t
+    fn derivative(&self, t: f64, state: DVec4) -> DVec4 {
+        let (accel1, accel2) = self.accelerations(self.effective_gravity(t), state.x, state.y, state.z, state.w);
+        DVec4::new(state.z, state.w, accel1, accel2)
+    }
+}
+
+impl DoublePendulum {
+    // the state of the pendulum at time t, as it's written to a trajectory
+    pub fn record_row(&self, t: f64) -> (f64, f64, f64, f64, f64) {
+        (t, self.theta1, self.theta2, self.angular1, self.angular2)
+    }
+
+    // the angles and angular velocities
+    pub fn state(&self) -> (f64, f64, f64, f64) {
+        (self.theta1, self.theta2, self.angular1, self.angular2)
+    }
+}
+
+// the mechanical energy of the pendulum
+// the potential energy is zero at the height of the origin
+impl DoublePendulum {
+    pub fn kinetic_energy(&self) -> f64 {
+        let (l1, l2) = (self.length1, self.length2);
+        let (w1, w2) = (self.angular1, self.angular2);
+        0.5 * self.mass1 * (l1 * w1).powi(2)
+        + 0.5 * self.mass2 * (
+            (l1 * w1).powi(2) + (l2 * w2).powi(2)
+            + 2.0 * l1 * l2 * w1 * w2 * (self.theta1 - self.theta2).cos()
+        )
+    }
+
+    // the height is measured along the way gravity pulls, if it's tilted
+    pub fn potential_energy(&self) -> f64 {
+        -(self.mass1 + self.mass2) * self.gravity * self.length1 * (self.theta1 - self.gravity_angle).cos()
+        - self.mass2 * self.gravity * self.length2 * (self.theta2 - self.gravity_angle).cos()
+    }
+
+    pub fn total_energy(&self) -> f64 {
+        self.kinetic_energy() + self.potential_energy()
+    }
+}
+
+// the angular momentum of both bobs about the pivot, positive in the
+// direction the angles increase
+// gravity, damping and the drive all exert a torque about the pivot, so it's
+// only conserved without them
+impl DoublePendulum {
+    pub fn angular_momentum(&self) -> f64 {
+        let (l1, l2) = (self.length1, self.length2);
+        let (w1, w2) = (self.angular1, self.angular2);
+        self.mass1 * l1 * l1 * w1
+        + self.mass2 * (
+            l1 * l1 * w1 + l2 * l2 * w2
+            + l1 * l2 * (w1 + w2) * (self.theta1 - self.theta2).cos()
+        )
+    }
+}
+
+// the speed of the outer bob
+impl DoublePendulum {
+    // in any state, not just the current one
+    fn speed_at(&self, theta1: f64, theta2: f64, w1: f64, w2: f64) -> f64 {
+        let (l1, l2) = (self.length1, self.length2);
+        (
+            (l1 * w1).powi(2) + (l2 * w2).powi(2)
+            + 2.0 * l1 * l2 * w1 * w2 * (theta1 - theta2).cos()
+        ).max(0.0).sqrt()
+    }
+}
+
+// how far the pivot has moved down from the origin, in units of length
+impl DoublePendulum {
+    pub fn pivot_offset(&self) -> f64 {
+        self.drive_amp * (self.drive_freq * self.time).sin()
+    }
+}
+
+// these functions get the endpoints of each rod, relative to the pivot
+// scale is the length of a unit of length, ex, in pixels
+// useful for drawing the double pendulum
+impl DoublePendulum {
+    pub fn dx1(&self, scale: f64) -> f64 {
+        self.length1 * scale * self.theta1.sin()
+    }
+
+    pub fn dy1(&self, scale: f64) -> f64 {
+        self.length1 * scale * self.theta1.cos()
+    }
+
+    pub fn dx2(&self, scale: f64) -> f64 {
+        self.dx1(scale) + self.length2 * scale * self.theta2.sin()
+    }
+
+    pub fn dy2(&self, scale: f64) -> f64 {
+        self.dy1(scale) + self.length2 * scale * self.theta2.cos()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DT: f64 = 1.0 / 240.0;
+
+    // the equations of motion as they were before the lengths and masses
+    // were split, which only hold for rods of 1 and equal masses
+    // https://www.diego.assencio.com/?index=1500c66ae7ab27bb0106467c68feebc6
+    fn simplified_derivative(state: DVec4) -> DVec4 {
+        let (theta1, theta2, angular1, angular2) = (state.x, state.y, state.z, state.w);
+        let a1 = (theta1 - theta2).cos() / 2.0;
+        let a2 = (theta1 - theta2).cos();
+        let f1 = -(angular2 * angular2) * (theta1 - theta2).sin() / 2.0 - GRAVITY * theta1.sin();
+        let f2 = (angular1 * angular1) * (theta1 - theta2).sin() - GRAVITY * theta2.sin();
+        DVec4::new(
+            angular1,
+            angular2,
+            (f1 - a1 * f2) / (1.0 - a1 * a2),
+            (-a2 * f1 + f2) / (1.0 - a1 * a2),
+        )
+    }
+
+    fn simplified_step(state: DVec4, dt: f64) -> DVec4 {
+        let k1 = simplified_derivative(state);
+        let k2 = simplified_derivative(state + (dt / 2.0) * k1);
+        let k3 = simplified_derivative(state + (dt / 2.0) * k2);
+        let k4 = simplified_derivative(state + dt * k3);
+        state + (dt / 6.0) * (k1 + 2.0 * k2 + 2.0 * k3 + k4)
+    }
+
+    fn state_of(dp: &DoublePendulum) -> DVec4 {
+        DVec4::new(dp.theta1, dp.theta2, dp.angular1, dp.angular2)
+    }
+
+    // the largest difference between two states, with the angles compared
+    // the short way round
+    fn difference(a: DVec4, b: DVec4) -> f64 {
+        wrap_angle(a.x - b.x).abs()
+            .max(wrap_angle(a.y - b.y).abs())
+            .max((a.z - b.z).abs())
+            .max((a.w - b.w).abs())
+    }
+
+    // the state after a number of steps of DT, as it was worked out when
+    // these were written, so any change to the physics shows up here
+    fn assert_reaches(dp: DoublePendulum, steps: usize, expected: (f64, f64, f64, f64)) {
+        let mut dp = dp;
+        for _ in 0..steps {
+            dp.update(DT);
+        }
+        let expected = DVec4::new(expected.0, expected.1, expected.2, expected.3);
+        assert!(difference(state_of(&dp), expected) < 1e-9, "{:?} isn't {:?}", state_of(&dp), expected);
+    }
+
+    #[test]
+    fn a_chaotic_start_reaches_the_reference_state() {
+        assert_reaches(
+            DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 170f64.to_radians(), 100f64.to_radians()),
+            240,
+            (-0.4267721372977511, 0.6002277055383685, -7.360696420939822, 3.483427535365845),
+        );
+    }
+
+    #[test]
+    fn a_start_near_equilibrium_reaches_the_reference_state() {
+        assert_reaches(
+            DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 0.05, -0.03),
+            480,
+            (0.019894021205676626, -0.024786543624148116, 0.20948273808147333, -0.1989744272858188),
+        );
+    }
+
+    #[test]
+    fn equal_rods_follow_the_simplified_equations() {
+        let mut dp = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 2.0, 0.5);
+        let mut reference = state_of(&dp);
+        for _ in 0..240 {
+            dp.update(DT);
+            reference = simplified_step(reference, DT);
+        }
+        assert!(difference(state_of(&dp), reference) < 1e-10);
+    }
+
+    #[test]
+    fn equal_masses_of_any_size_follow_the_simplified_equations() {
+        let mut dp = DoublePendulum::new(1.0, 1.0, 2.5, 2.5, 2.0, 0.5);
+        let mut reference = state_of(&dp);
+        for _ in 0..240 {
+            dp.update(DT);
+            reference = simplified_step(reference, DT);
+        }
+        assert!(difference(state_of(&dp), reference) < 1e-10);
+    }
+
+    #[test]
+    fn energy_drifts_no_more_than_with_the_simplified_equations() {
+        let mut dp = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 2.0, 0.5);
+        let mut simplified = dp.clone();
+        let start = dp.total_energy();
+        let mut reference = state_of(&dp);
+        for _ in 0..1000 {
+            dp.update(DT);
+            reference = simplified_step(reference, DT);
+        }
+        simplified.set_state(reference);
+        let drift = (dp.total_energy() - start).abs();
+        let simplified_drift = (simplified.total_energy() - start).abs();
+        assert!(drift < 1e-5);
+        assert!((drift - simplified_drift).abs() < 1e-9);
+    }
+
+    #[test]
+    fn adaptive_step_matches_many_short_rk4_steps() {
+        let mut adaptive = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 2.0, 0.5);
+        let mut fixed = adaptive.clone();
+        adaptive.update_adaptive(0.1, 1e-12);
+        for _ in 0..100 {
+            fixed.update(0.001);
+        }
+        assert!(difference(state_of(&adaptive), state_of(&fixed)) < 1e-9);
+        assert!((adaptive.time - 0.1).abs() < 1e-12);
+    }
+
+    #[test]
+    fn adaptive_step_diverges_instead_of_hanging() {
+        let mut dp = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, f64::NAN, 0.5);
+        dp.update_adaptive(0.1, 1e-9);
+        assert!(dp.diverged);
+        assert_eq!(dp.time, 0.0);
+    }
+
+    #[test]
+    fn adaptive_step_runs_backwards() {
+        let mut dp = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 2.0, 0.5).with_integrator(Integrator::DormandPrince);
+        let start = state_of(&dp);
+        dp.update(0.5);
+        assert!(difference(state_of(&dp), start) > 0.1);
+        dp.update(-0.5);
+        assert!(difference(state_of(&dp), start) < 1e-6);
+        assert!(dp.time.abs() < 1e-12);
+    }
+
+    #[test]
+    fn long_updates_are_split_into_substeps() {
+        let mut long = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 1.0, 0.5);
+        let mut short = long.clone();
+        long.update(0.1);
+        for _ in 0..10 {
+            short.update(0.01);
+        }
+        assert!(difference(state_of(&long), state_of(&short)) < 1e-5);
+    }
+
+    #[test]
+    fn damping_only_ever_loses_energy() {
+        let mut dp = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 2.0, 0.5).with_damping(0.5);
+        let mut energy = dp.total_energy();
+        for _ in 0..2400 {
+            dp.update(DT);
+            assert!(dp.total_energy() < energy);
+            energy = dp.total_energy();
+        }
+    }
+
+    #[test]
+    fn energy_is_conserved_without_damping() {
+        let mut dp = DoublePendulum::new(1.0, 0.7, 1.0, 2.0, 2.0, -1.0).with_substeps(4);
+        let start = dp.total_energy();
+        for _ in 0..2400 {
+            dp.update(DT);
+        }
+        assert!(((dp.total_energy() - start) / start).abs() < 1e-6);
+    }
+
+    // at small angles the pendulum swings in two normal modes, with equal
+    // rods and masses the slower one has the outer rod sqrt(2) times as far
+    // out as the inner one, and an angular frequency of sqrt((2 - sqrt(2)) * g / l)
+    #[test]
+    fn the_slow_normal_mode_has_the_small_angle_period() {
+        let amplitude = 0.01;
+        let mut dp = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, amplitude, amplitude * 2f64.sqrt());
+        let period = std::f64::consts::TAU / ((2.0 - 2f64.sqrt()) * GRAVITY).sqrt();
+        let steps = 1000;
+        for _ in 0..steps / 2 {
+            dp.update(period / steps as f64);
+        }
+        assert!((dp.theta1 + amplitude).abs() < 1e-5);
+        for _ in 0..steps / 2 {
+            dp.update(period / steps as f64);
+        }
+        assert!((dp.theta1 - amplitude).abs() < 1e-5);
+        assert!((dp.theta2 - amplitude * 2f64.sqrt()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn angular_momentum_is_conserved_without_gravity_or_damping() {
+        let mut dp = DoublePendulum::new(1.0, 0.7, 1.0, 2.0, 2.0, -1.0).with_gravity(0.0).with_substeps(4);
+        dp.angular1 = 3.0;
+        dp.angular2 = -5.0;
+        let start = dp.angular_momentum();
+        for _ in 0..2400 {
+            dp.update(DT);
+        }
+        assert!((dp.angular_momentum() - start).abs() < 1e-6 * start.abs());
+    }
+
+    #[test]
+    fn angles_stay_wrapped_over_a_million_steps() {
+        let mut dp = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 2.0, 0.5);
+        dp.angular1 = 20.0;
+        for _ in 0..1_000_000 {
+            dp.update(DT);
+            assert!((-std::f64::consts::PI..std::f64::consts::PI).contains(&dp.theta1));
+            assert!((-std::f64::consts::PI..std::f64::consts::PI).contains(&dp.theta2));
+        }
+        assert!(!dp.diverged);
+    }
+
+    #[test]
+    fn wrapping_leaves_the_bobs_where_they_are() {
+        let mut dp = DoublePendulum::new(1.0, 0.7, 1.0, 1.0, 2.0 + 5.0 * std::f64::consts::TAU, -0.5 - 3.0 * std::f64::consts::TAU);
+        let unwrapped = (dp.dx1(1.0), dp.dy1(1.0), dp.dx2(1.0), dp.dy2(1.0));
+        dp.set_state(state_of(&dp));
+        assert!(dp.theta1.abs() < std::f64::consts::PI && dp.theta2.abs() < std::f64::consts::PI);
+        let wrapped = (dp.dx1(1.0), dp.dy1(1.0), dp.dx2(1.0), dp.dy2(1.0));
+        assert!((wrapped.0 - unwrapped.0).abs() < 1e-12);
+        assert!((wrapped.1 - unwrapped.1).abs() < 1e-12);
+        assert!((wrapped.2 - unwrapped.2).abs() < 1e-12);
+        assert!((wrapped.3 - unwrapped.3).abs() < 1e-12);
+    }
+
+    #[test]
+    fn shared_intermediates_give_the_same_accelerations() {
+        let params = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 0.0, 0.0).params();
+        for i in 0..100 {
+            let i = i as f64;
+            let state = DVec4::new((i * 0.7).sin() * 3.0, (i * 1.3).cos() * 3.0, (i * 0.4).sin() * 8.0, (i * 2.1).cos() * 8.0);
+            let (new, old) = (params.derivative(0.0, state), simplified_derivative(state));
+            assert!((new - old).abs().max_element() < 1e-12 * old.abs().max_element().max(1.0));
+        }
+    }
+
+    // with a massless inner bob the denominator is 2 * m2 * sin(delta)^2,
+    // which is zero whenever the rods line up
+    #[test]
+    fn a_nearly_massless_inner_bob_stays_finite() {
+        let mut dp = DoublePendulum::new(1.0, 1.0, 1e-12, 1.0, 1.0, 1.0);
+        for _ in 0..24000 {
+            dp.update(DT);
+            assert!(state_of(&dp).is_finite());
+        }
+        assert!(!dp.diverged);
+    }
+
+    #[test]
+    fn an_absurd_timestep_diverges_instead_of_going_nan() {
+        let mut dp = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 2.0, 0.5);
+        let start = state_of(&dp);
+        dp.update(1e300);
+        assert!(dp.diverged);
+        assert_eq!(state_of(&dp), start);
+        assert_eq!(dp.time, 0.0);
+
+        // and it stays where it is from then on
+        dp.update(DT);
+        assert_eq!(state_of(&dp), start);
+        assert!(dp.prev_angles.iter().all(|&(theta1, theta2, angular1, angular2, _)| {
+            DVec4::new(theta1, theta2, angular1, angular2).is_finite()
+        }));
+    }
+
+    #[test]
+    fn a_full_trace_never_reallocates() {
+        let mut dp = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 2.0, 0.5);
+        for _ in 0..1000 {
+            dp.update(DT);
+        }
+        assert_eq!(dp.prev_angles.len(), 150);
+        let capacity = dp.prev_angles.capacity();
+        for _ in 0..10000 {
+            dp.update(DT);
+            assert_eq!(dp.prev_angles.capacity(), capacity);
+        }
+    }
+
+    #[test]
+    fn no_quadratic_drag_is_the_same_as_none_at_all() {
+        let mut undamped = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 2.0, 0.5);
+        let mut dragged = undamped.clone().with_drag(Drag::Quadratic).with_drag_coeff(0.0);
+        for _ in 0..2400 {
+            undamped.update(DT);
+            dragged.update(DT);
+        }
+        assert_eq!(state_of(&undamped), state_of(&dragged));
+    }
+
+    #[test]
+    fn quadratic_drag_loses_energy_faster_at_high_speeds() {
+        let spinning = |dp: DoublePendulum| {
+            let mut dp = dp;
+            dp.angular1 = 10.0;
+            dp.angular2 = -10.0;
+            dp
+        };
+        let start = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 2.0, 0.5);
+        let mut linear = spinning(start.clone().with_damping(0.1));
+        let mut quadratic = spinning(start.clone().with_drag(Drag::Quadratic).with_drag_coeff(0.1));
+        let energy = linear.total_energy();
+        for _ in 0..120 {
+            linear.update(DT);
+            quadratic.update(DT);
+        }
+        assert!(energy - quadratic.total_energy() > 2.0 * (energy - linear.total_energy()));
+    }
+
+    // the energy drift after ten seconds of frames of a 60th of a second
+    fn drift(dp: DoublePendulum) -> f64 {
+        let mut dp = dp;
+        let start = dp.total_energy();
+        for _ in 0..600 {
+            dp.update(1.0 / 60.0);
+        }
+        (dp.total_energy() - start).abs()
+    }
+
+    #[test]
+    fn more_substeps_drift_less() {
+        let start = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 2.0, 0.5);
+        let drifts: Vec<f64> = [1, 2, 4].iter().map(|&substeps| drift(start.clone().with_substeps(substeps))).collect();
+        assert!(drifts[1] < drifts[0] && drifts[2] < drifts[1]);
+    }
+
+    #[test]
+    fn euler_gains_energy_while_rk4_holds_it() {
+        let start = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 1.0, 0.5);
+        let energy = start.total_energy();
+        let mut euler = start.clone().with_integrator(Integrator::Euler);
+        let mut rk4 = start.with_integrator(Integrator::Rk4);
+        for _ in 0..2400 {
+            euler.update(DT);
+            rk4.update(DT);
+        }
+        assert!(euler.total_energy() - energy > 0.1 * energy.abs());
+        assert!((rk4.total_energy() - energy).abs() < 1e-6 * energy.abs());
+    }
+
+    #[test]
+    fn every_substep_is_sampled_at_a_trace_rate_of_zero() {
+        let mut dp = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 2.0, 0.5).with_trace_rate(0.0).with_substeps(8);
+        dp.update(DT);
+        assert_eq!(dp.prev_angles.len(), 8);
+        dp.update(DT);
+        assert_eq!(dp.prev_angles.len(), 16);
+
+        // and the oldest are dropped once there are too many
+        dp.set_max_trace(20);
+        dp.update(DT);
+        assert_eq!(dp.prev_angles.len(), 20);
+    }
+
+    // r x mv of each bob, with y pointing down so a positive angular
+    // velocity is a positive angular momentum
+    #[test]
+    fn angular_momentum_is_the_sum_over_the_bobs() {
+        let mut dp = DoublePendulum::new(1.2, 0.7, 1.5, 0.5, 2.0, -1.0);
+        dp.angular1 = 3.0;
+        dp.angular2 = -5.0;
+        let (x1, y1) = (dp.dx1(1.0), dp.dy1(1.0));
+        let (x2, y2) = (dp.dx2(1.0), dp.dy2(1.0));
+        let (vx1, vy1) = (dp.length1 * dp.angular1 * dp.theta1.cos(), -dp.length1 * dp.angular1 * dp.theta1.sin());
+        let (vx2, vy2) = (vx1 + dp.length2 * dp.angular2 * dp.theta2.cos(), vy1 - dp.length2 * dp.angular2 * dp.theta2.sin());
+        let expected = dp.mass1 * (y1 * vx1 - x1 * vy1) + dp.mass2 * (y2 * vx2 - x2 * vy2);
+        assert!((dp.angular_momentum() - expected).abs() < 1e-12);
+    }
+
+    // the error of RK4 over a fixed time shrinks with the fourth power of
+    // dt, so twice as many steps of half the length are about 16 times closer
+    // the drive is on so the phase t is checked too
+    #[test]
+    fn halving_the_step_shrinks_the_error_sixteen_times() {
+        let params = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 0.0, 0.0).with_drive(0.1, 5.0).params();
+        let start = DVec4::new(1.0, 0.5, 0.0, 0.0);
+        let run = |steps: usize| {
+            let dt = 0.5 / steps as f64;
+            (0..steps).fold(start, |state, i| step(state, i as f64 * dt, dt, &params))
+        };
+        let exact = run(4096);
+        let error = |steps| difference(run(steps), exact);
+        let ratio = error(32) / error(64);
+        assert!((12.0..20.0).contains(&ratio), "the error only shrank {} times", ratio);
+    }
+
+    #[test]
+    fn the_poincare_section_stops_growing() {
+        let mut dp = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 0.0, 0.0);
+        let (before, after) = (DVec4::new(-0.1, 0.0, 1.0, 0.0), DVec4::new(0.1, 0.0, 1.0, 0.0));
+        for i in 0..MAX_POINCARE + 10 {
+            dp.record_crossing(before, after + DVec4::new(0.0, i as f64, 0.0, 0.0));
+        }
+        assert_eq!(dp.poincare.len(), MAX_POINCARE);
+        // the oldest crossings are the ones dropped
+        assert_eq!(dp.poincare.front().unwrap().0, 5.0);
+    }
+
+    #[test]
+    fn a_starting_spin_is_carried_into_the_first_step() {
+        let mut dp = DoublePendulum::builder().angles(1.0, 0.5).angular_velocities(3.0, -2.0).build();
+        assert_eq!((dp.angular1, dp.angular2), (3.0, -2.0));
+        let expected = step(DVec4::new(1.0, 0.5, 3.0, -2.0), 0.0, DT, &dp.params());
+        dp.update(DT);
+        assert_eq!(state_of(&dp), DVec4::new(wrap_angle(expected.x), wrap_angle(expected.y), expected.z, expected.w));
+        // and it moved the way it was spun
+        assert!(dp.theta1 > 1.0 && dp.theta2 < 0.5);
+    }
+}
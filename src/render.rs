@@ -0,0 +1,729 @@
+// render.rs
+
+// drawing the pendulums with macroquad
+// the colors live here rather than in the physics, one for each pendulum
+
+use macroquad::prelude::*;
+use macroquad::models::{Mesh, Vertex, draw_mesh};
+use colorsys::{Rgb, Hsl};
+use serde::{Deserialize, Serialize};
+
+use crate::lyapunov;
+use crate::pendulum::{DoublePendulum, wrap_angle};
+
+
+// how the parts of the pendulums are drawn, with the sizes in pixels
+#[derive(Clone, Copy)]
+pub struct Style {
+    pub rod: f32,
+    // the radius of a bob with a mass of 1
+    pub node: f32,
+    // the thickness of the newest part of a trace, each older segment is
+    // thinner than the one before it by a factor of decay
+    pub trace: f32,
+    pub decay: f32,
+    // only every stride-th position of a trace is drawn, which is quicker
+    // but less smooth, 1 draws all of them
+    pub stride: usize,
+    pub fade: Fade,
+    // smoother bobs with shadows and rounded rods, which is slower
+    pub pretty: bool,
+}
+
+// a position of a trace, (theta1, theta2, angular1, angular2, speed)
+type TracePoint = (f64, f64, f64, f64, f64);
+
+// how thick the oldest segment of a trace is compared to the newest
+const TAIL_THICKNESS: f32 = 0.1;
+
+// the decay that thins a trace of max_trace positions down to TAIL_THICKNESS
+// at its oldest segment, so the tail looks the same however long it is
+pub fn tail_decay(max_trace: usize) -> f32 {
+    let oldest = max_trace.saturating_sub(2).max(1);
+    TAIL_THICKNESS.powf(1.0 / oldest as f32)
+}
+
+// how far the shadows of the pretty style fall from the bobs, in pixels
+const SHADOW_OFFSET: f32 = 3.0;
+// the sides of the circles of the pretty style, macroquad's are 20
+const PRETTY_SIDES: u8 = 48;
+
+// how a trace fades out from the bob towards its oldest end
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Fade {
+    // the whole trace keeps the color of its pendulum
+    Off,
+    // the alpha falls evenly to nothing at the oldest end
+    Linear,
+    // the alpha falls quickly near the bob and slowly after, to nothing at the oldest end
+    Exponential,
+}
+
+impl Fade {
+    // the alpha of a segment a fraction of the way along the trace, from 0 at the bob to 1 at the end
+    fn alpha(self, alpha: f32, fraction: f32) -> f32 {
+        match self {
+            Fade::Off => alpha,
+            Fade::Linear => alpha * (1.0 - fraction),
+            // shifted down and stretched back up so it still starts at 1 but ends at 0
+            Fade::Exponential => {
+                let end = (-4.0f32).exp();
+                alpha * (((-4.0 * fraction).exp() - end) / (1.0 - end)).max(0.0)
+            }
+        }
+    }
+}
+
+
+// how the pendulums are positioned on the screen
+// zooming scales the pendulums around their origin, and panning moves the origin
+pub struct Camera {
+    pub origin: (f32, f32),
+    // the amount of pixels per unit of length before zooming
+    pub fit: f64,
+    pub zoom: f32,
+    pub pan_x: f32,
+    pub pan_y: f32,
+}
+
+impl Camera {
+    pub fn new() -> Camera {
+        Camera { origin: (0.0, 0.0), fit: 100.0, zoom: 1.0, pan_x: 0.0, pan_y: 0.0 }
+    }
+
+    // the amount of pixels per unit of length
+    pub fn scale(&self) -> f64 {
+        self.fit * self.zoom as f64
+    }
+
+    // the origin on the screen, after it has been panned
+    pub fn screen_origin(&self) -> (f32, f32) {
+        (self.origin.0 + self.pan_x, self.origin.1 + self.pan_y)
+    }
+}
+
+
+// the pixels per unit of length that make a pendulum reaching reach units
+// from its pivot fill two thirds of the shorter side of the window, so it
+// fits however the window is shaped, a 600 by 600 window with rods of 1
+// gives 100 pixels per unit
+pub fn fit_scale(width: f32, height: f32, reach: f64) -> f64 {
+    width.min(height) as f64 / (3.0 * reach.max(1e-3))
+}
+
+
+// where a pendulum hangs from on the screen, which moves with a driven pivot
+// the trace is drawn from here too, so it follows the pivot rather than
+// staying where the bob was
+pub fn pivot(dp: &DoublePendulum, camera: &Camera) -> (f32, f32) {
+    let (x, y) = camera.screen_origin();
+    (x, y + (dp.pivot_offset() * camera.scale()) as f32)
+}
+
+
+// where the pivot, the inner bob and the outer bob of a pendulum are on the screen
+pub fn joint_positions(dp: &DoublePendulum, camera: &Camera) -> ((f32, f32), (f32, f32), (f32, f32)) {
+    let scale = camera.scale();
+    let (ox, oy) = pivot(dp, camera);
+    (
+        (ox, oy),
+        (ox + dp.dx1(scale) as f32, oy + dp.dy1(scale) as f32),
+        (ox + dp.dx2(scale) as f32, oy + dp.dy2(scale) as f32),
+    )
+}
+
+
+// the ways of coloring a fan of pendulums, from the first to the last
+// viridis and turbo are perceptually uniform, so the bands where the
+// pendulums split apart are easier to see
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Colormap {
+    // a sweep through the hues
+    Hsl,
+    Viridis,
+    Turbo,
+    // black to light gray, so the last pendulum still shows on a light background
+    Grayscale,
+}
+
+impl Colormap {
+    pub fn next(self) -> Colormap {
+        match self {
+            Colormap::Hsl => Colormap::Viridis,
+            Colormap::Viridis => Colormap::Turbo,
+            Colormap::Turbo => Colormap::Grayscale,
+            Colormap::Grayscale => Colormap::Hsl,
+        }
+    }
+}
+
+// the color of pendulum index out of count, fully opaque
+pub fn color_for(index: usize, count: usize, map: Colormap) -> Color {
+    let t = index as f64 / (count.max(2) - 1) as f64;
+    let (r, g, b) = match map {
+        Colormap::Hsl => {
+            let c = Rgb::from(Hsl::new(255.0 * t, 100.0, 80.0, None));
+            (c.red() / 255.0, c.green() / 255.0, c.blue() / 255.0)
+        }
+        Colormap::Viridis => viridis(t),
+        Colormap::Turbo => turbo(t),
+        Colormap::Grayscale => (0.8 * t, 0.8 * t, 0.8 * t),
+    };
+    Color::new(r.clamp(0.0, 1.0) as f32, g.clamp(0.0, 1.0) as f32, b.clamp(0.0, 1.0) as f32, 1.0)
+}
+
+// the colors of a fan of pendulums
+// they're see-through so the fan shows where the pendulums overlap
+pub fn fan_colors(dp_count: usize, map: Colormap, alpha: f32) -> Vec<Color> {
+    (0..dp_count).map(|i| Color { a: alpha, ..color_for(i, dp_count, map) }).collect()
+}
+
+// the closest separation from the first pendulum that is told apart from none
+const MIN_SEPARATION: f64 = 1e-6;
+
+// colors every pendulum by its separation from the first, from green while
+// they're still together to red once they've fully diverged
+// the separations grow exponentially, so they're mapped on a log scale
+pub fn divergence_colors(dp_vec: &[DoublePendulum], alpha: f32) -> Vec<Color> {
+    let (min, max) = (MIN_SEPARATION.ln(), lyapunov::SATURATION.ln());
+    dp_vec.iter().map(|dp| {
+        let d = lyapunov::separation(&dp_vec[0], dp).max(MIN_SEPARATION);
+        let fraction = ((d.ln() - min) / (max - min)).clamp(0.0, 1.0);
+        let c = Rgb::from(Hsl::new(120.0 * (1.0 - fraction), 100.0, 45.0, None));
+        Color::new(c.red() as f32 / 255.0, c.green() as f32 / 255.0, c.blue() as f32 / 255.0, alpha)
+    }).collect()
+}
+
+// the background of the dark theme
+pub const DARK_BACKGROUND: Color = Color::new(0.05, 0.05, 0.07, 1.0);
+
+// the transparency of the fan, which needs to be more opaque to stand out
+// against a dark background
+pub fn fan_alpha(dark_mode: bool) -> f32 {
+    if dark_mode { 0.5 } else { 0.25 }
+}
+
+// black text on light backgrounds and light text on dark ones
+// based on the relative luminance of the background
+pub fn text_color(background: Color) -> Color {
+    let luminance = 0.2126 * background.r + 0.7152 * background.g + 0.0722 * background.b;
+    if luminance > 0.5 { BLACK } else { Color::new(0.9, 0.9, 0.9, 1.0) }
+}
+
+// evaluates a polynomial in t for each channel, coefficients from the lowest power
+fn polynomial(t: f64, coefficients: &[(f64, f64, f64)]) -> (f64, f64, f64) {
+    coefficients.iter().rev().fold((0.0, 0.0, 0.0), |(r, g, b), (cr, cg, cb)| {
+        (r * t + cr, g * t + cg, b * t + cb)
+    })
+}
+
+// a polynomial fit of viridis
+// https://www.shadertoy.com/view/WlfXRN
+fn viridis(t: f64) -> (f64, f64, f64) {
+    polynomial(t, &[
+        (0.2777273272234177, 0.005407344544966578, 0.3340998053353061),
+        (0.1050930431085774, 1.404613529898575, 1.384590162594685),
+        (-0.3308618287255563, 0.214847559468213, 0.09509516302823659),
+        (-4.634230498983486, -5.799100973351585, -19.33244095627987),
+        (6.228269936347081, 14.17993336680509, 56.69055260068105),
+        (4.776384997670288, -13.74514537774601, -65.35303263337234),
+        (-5.435455855934631, 4.645852612178535, 26.3124352495832),
+    ])
+}
+
+// a polynomial fit of turbo
+// https://gist.github.com/mikhailov-work/0d177465a8151eb6ede1768d51d476c7
+fn turbo(t: f64) -> (f64, f64, f64) {
+    polynomial(t, &[
+        (0.13572138, 0.09140261, 0.10667330),
+        (4.61539260, 2.19418839, 12.64194608),
+        (-42.66032258, 4.84296658, -60.58204836),
+        (132.13108234, -14.18503333, 110.36276771),
+        (-152.94239396, 4.27729857, -89.90310912),
+        (59.28637943, 2.82956604, 27.34824973),
+    ])
+}
+
+
+pub fn draw(dp: &DoublePendulum, color: Color, camera: &Camera, style: &Style) {
+    let color = shown_color(dp, color);
+    let radius = style.node;
+    let thickness = style.rod;
+
+    // let line_color = Color::new(0.7, 0.7, 0.7, 0.5);
+    // let node_color = Color::new(0.0, 0.0, 0.0, 1.0);
+
+    // let line_color = Color::new(0.08, 0.05, 0.05, 1.0);
+    // let node_color = Color::new(0.08, 0.05, 0.05, 1.0);
+
+    let line_color = color;
+    let node_color = color;
+
+    let ((ox, oy), (x1, y1), (x2, y2)) = joint_positions(dp, camera);
+
+    // the bobs are scaled so that their area is proportional to their mass
+    let nodes = [
+        (ox, oy, radius),
+        (x1, y1, radius * dp.mass1.sqrt() as f32),
+        (x2, y2, radius * dp.mass2.sqrt() as f32),
+    ];
+
+    if style.pretty {
+        // a soft shadow down and to the right, under the rest of the pendulum
+        let shadow = Color::new(0.0, 0.0, 0.0, 0.2 * color.a);
+        for (x, y, r) in nodes {
+            draw_poly(x + SHADOW_OFFSET, y + SHADOW_OFFSET, PRETTY_SIDES, r * 1.1, 0.0, shadow);
+        }
+    }
+    draw_line(ox, oy, x1, y1, thickness, line_color);
+    draw_line(x1, y1, x2, y2, thickness, line_color);
+    if style.pretty {
+        // rounds off the ends of the rods, which show when the bobs are small
+        for (x, y, _) in nodes {
+            draw_poly(x, y, PRETTY_SIDES, thickness / 2.0, 0.0, line_color);
+        }
+        for (x, y, r) in nodes {
+            draw_poly(x, y, PRETTY_SIDES, r, 0.0, node_color);
+        }
+    } else {
+        for (x, y, r) in nodes {
+            draw_circle(x, y, r, node_color);
+        }
+    }
+    // draw_circle(ox + dx2, oy + dy2, radius, color);
+}
+
+// draws a faint circle around the pivot as far as the outer bob can reach,
+// the space the traces fill
+pub fn draw_reach(dp: &DoublePendulum, color: Color, camera: &Camera) {
+    let (x, y) = pivot(dp, camera);
+    let radius = ((dp.length1 + dp.length2) * camera.scale()) as f32;
+    draw_circle_lines(x, y, radius, 1.0, Color { a: 0.2, ..color });
+}
+
+// draws a faint outline of a pendulum, under everything else
+pub fn draw_ghost(dp: &DoublePendulum, color: Color, camera: &Camera, style: &Style) {
+    let style = Style { rod: style.rod * 0.5, node: style.node * 0.75, ..*style };
+    draw(dp, Color { a: 0.2, ..color }, camera, &style);
+}
+
+// draws the hero pendulum over the fan, opaque and with thicker rods
+pub fn draw_hero(dp: &DoublePendulum, color: Color, camera: &Camera, style: &Style) {
+    let style = Style { rod: style.rod * 1.5, node: style.node * 1.25, ..*style };
+    draw(dp, Color { a: 1.0, ..color }, camera, &style);
+}
+
+// draws the traces of every pendulum
+// with hundreds of pendulums there are tens of thousands of segments, so
+// they're gathered into meshes instead of being drawn one line at a time
+// the hero's trace is opaque and added last, so it's drawn on top
+pub fn draw_traces(
+    dp_vec: &[DoublePendulum], colors: &[Color], camera: &Camera, style: &Style,
+    hero: Option<usize>, trace_inner: bool, speed_colors: bool,
+) {
+    let mut lines = LineMesh::new();
+    for (i, (dp, color)) in dp_vec.iter().zip(colors.iter()).enumerate() {
+        if hero != Some(i) {
+            trace(&mut lines, dp, *color, camera, style, trace_inner, speed_colors);
+        }
+    }
+    if let Some(i) = hero {
+        trace(&mut lines, &dp_vec[i], Color { a: 1.0, ..colors[i] }, camera, style, trace_inner, speed_colors);
+    }
+    lines.flush();
+}
+
+// also traces the inner bob if trace_inner is set
+// if speed_colors is set, the outer trace is colored by the speed of the bob
+// instead of the color of the pendulum
+fn trace(lines: &mut LineMesh, dp: &DoublePendulum, color: Color, camera: &Camera, style: &Style, trace_inner: bool, speed_colors: bool) {
+    // let trace_color = Color::new(0.3, 0.3, 1.0, 1.0);
+    // let trace_color = Color::new(0.545, 0.867, 0.945, 0.25);
+    let trace_color = shown_color(dp, color);
+
+    let scale = camera.scale();
+    let (ox, oy) = pivot(dp, camera);
+
+    // the trace starts thick at the newest position, next to the bob, and
+    // thins out towards the oldest, like the tail of a comet
+    let segments = dp.prev_angles.len().saturating_sub(1).max(1) as f32;
+    trace_segments(dp, style, |n, (a1, a2, _, _, speed), (b1, b2, _, _, _), thickness| {
+        let t1x = scale * (dp.length1 * a1.sin() + dp.length2 * a2.sin());
+        let t1y = scale * (dp.length1 * a1.cos() + dp.length2 * a2.cos());
+
+        let t2x = scale * (dp.length1 * b1.sin() + dp.length2 * b2.sin());
+        let t2y = scale * (dp.length1 * b1.cos() + dp.length2 * b2.cos());
+
+        let mut segment_color = if speed_colors && !dp.diverged { speed_color(dp, speed, color.a) } else { trace_color };
+        segment_color.a = style.fade.alpha(segment_color.a, n as f32 / segments);
+        lines.line(ox + t1x as f32, oy + t1y as f32, ox + t2x as f32, oy + t2y as f32, thickness, segment_color);
+    });
+
+    // the inner bob is traced in a dimmer shade so the outer trace stands out
+    if trace_inner {
+        let inner_color = Color::new(trace_color.r, trace_color.g, trace_color.b, trace_color.a * 0.5);
+
+        trace_segments(dp, style, |n, (a1, _, _, _, _), (b1, _, _, _, _), thickness| {
+            let t1x = scale * dp.length1 * a1.sin();
+            let t1y = scale * dp.length1 * a1.cos();
+
+            let t2x = scale * dp.length1 * b1.sin();
+            let t2y = scale * dp.length1 * b1.cos();
+
+            let segment_color = Color { a: style.fade.alpha(inner_color.a, n as f32 / segments), ..inner_color };
+            lines.line(ox + t1x as f32, oy + t1y as f32, ox + t2x as f32, oy + t2y as f32, thickness, segment_color);
+        });
+    }
+}
+
+// calls segment with the index, the ends and the thickness of each segment of
+// the trace to draw, from the newest to the oldest
+// with a stride, only every stride-th position is joined up, and the oldest
+// is always kept so the trace still reaches the end of its tail, each
+// segment is as thick as the first of the positions it skips over, so the
+// tail thins out the same whatever the stride
+fn trace_segments(dp: &DoublePendulum, style: &Style, mut segment: impl FnMut(usize, TracePoint, TracePoint, f32)) {
+    let (len, stride) = (dp.prev_angles.len(), style.stride.max(1));
+    let mut thickness = style.trace;
+    let mut i = 0;
+    while i + 1 < len {
+        let j = (i + stride).min(len - 1);
+        segment(i, dp.prev_angles[i], dp.prev_angles[j], thickness);
+        for _ in i..j {
+            thickness *= style.decay;
+        }
+        i = j;
+    }
+}
+
+// the trace of the outer bob as an svg image, for printing or plotting
+// it's laid out the same as on screen at a zoom of 1, around the pivot
+pub fn trace_svg(dp: &DoublePendulum, color: Color) -> String {
+    let scale = Camera::new().scale();
+    let reach = scale * (dp.length1 + dp.length2) + 10.0;
+    let mut path = String::new();
+    for (i, (a1, a2, _, _, _)) in dp.prev_angles.iter().enumerate() {
+        let x = scale * (dp.length1 * a1.sin() + dp.length2 * a2.sin());
+        let y = scale * (dp.length1 * a1.cos() + dp.length2 * a2.cos());
+        path.push_str(&format!("{}{:.3},{:.3} ", if i == 0 { "M" } else { "L" }, x, y));
+    }
+    let (r, g, b) = ((color.r * 255.0) as u8, (color.g * 255.0) as u8, (color.b * 255.0) as u8);
+    format!(
+        concat!(
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" viewBox=\"{0:.3} {0:.3} {1:.3} {1:.3}\">\n",
+            "  <path d=\"{2}\" fill=\"none\" stroke=\"rgb({3},{4},{5})\" stroke-width=\"2\" stroke-linejoin=\"round\"/>\n",
+            "</svg>\n",
+        ),
+        -reach, 2.0 * reach, path.trim_end(), r, g, b,
+    )
+}
+
+// a batch of lines drawn as one mesh, made of the same quads as draw_line
+// macroquad can't draw more than 10000 vertices or 5000 indices at once, so
+// the mesh is drawn and emptied whenever it's about to get too big
+struct LineMesh {
+    mesh: Mesh,
+}
+
+impl LineMesh {
+    const MAX_VERTICES: usize = 10000;
+    const MAX_INDICES: usize = 5000;
+
+    fn new() -> LineMesh {
+        LineMesh { mesh: Mesh { vertices: Vec::new(), indices: Vec::new(), texture: None } }
+    }
+
+    fn line(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, thickness: f32, color: Color) {
+        // offset both ends along the normal by half the thickness
+        let (nx, ny) = (y1 - y2, x2 - x1);
+        let length = (nx * nx + ny * ny).sqrt() / (thickness * 0.5);
+        if length < f32::EPSILON {
+            return;
+        }
+        let (tx, ty) = (nx / length, ny / length);
+
+        if self.mesh.vertices.len() + 4 >= Self::MAX_VERTICES || self.mesh.indices.len() + 6 >= Self::MAX_INDICES {
+            self.flush();
+        }
+
+        let first = self.mesh.vertices.len() as u16;
+        let vertex = |x: f32, y: f32| Vertex { position: vec3(x, y, 0.0), uv: vec2(0.0, 0.0), color };
+        self.mesh.vertices.extend_from_slice(&[
+            vertex(x1 + tx, y1 + ty),
+            vertex(x1 - tx, y1 - ty),
+            vertex(x2 + tx, y2 + ty),
+            vertex(x2 - tx, y2 - ty),
+        ]);
+        self.mesh.indices.extend([0, 1, 2, 2, 1, 3].iter().map(|i| first + i));
+    }
+
+    // draws the lines so far and starts a new batch
+    fn flush(&mut self) {
+        if !self.mesh.indices.is_empty() {
+            draw_mesh(&self.mesh);
+        }
+        self.mesh.vertices.clear();
+        self.mesh.indices.clear();
+    }
+}
+
+// the spacing between the lines of the hud, which is also their font size
+pub const HUD_LINE_HEIGHT: f32 = 20.0;
+
+// draws each line of the hud below the last, in the top left corner
+// on a translucent patch of the background, so traces passing under the
+// text don't make it unreadable
+pub fn draw_hud(lines: &[(String, Color)], background: Color) {
+    let width = lines.iter()
+        .map(|(text, _)| measure_text(text, None, HUD_LINE_HEIGHT as u16, 1.0).width)
+        .fold(0.0, f32::max);
+    let height = HUD_LINE_HEIGHT * (lines.len() as f32 + 0.5);
+    draw_rectangle(0.0, 0.0, width + 20.0, height, Color { a: 0.75, ..background });
+    for (i, (text, color)) in lines.iter().enumerate() {
+        draw_text(text, 10.0, HUD_LINE_HEIGHT * (i + 1) as f32, HUD_LINE_HEIGHT, *color);
+    }
+}
+
+// an arrow in the top right corner pointing the way gravity pulls, angle
+// radians from straight down
+pub fn draw_gravity_arrow(angle: f64, color: Color) {
+    let length = 24.0;
+    let (cx, cy) = (screen_width() - 40.0, 40.0);
+    let (dx, dy) = (angle.sin() as f32, angle.cos() as f32);
+    let (tail, tip) = ((cx - dx * length / 2.0, cy - dy * length / 2.0), (cx + dx * length / 2.0, cy + dy * length / 2.0));
+    draw_line(tail.0, tail.1, tip.0, tip.1, 2.0, color);
+    // the head is a triangle with the tip at the end of the line
+    let head = 8.0;
+    let back = (tip.0 - dx * head, tip.1 - dy * head);
+    draw_triangle(
+        vec2(tip.0, tip.1),
+        vec2(back.0 - dy * head / 2.0, back.1 + dx * head / 2.0),
+        vec2(back.0 + dy * head / 2.0, back.1 - dx * head / 2.0),
+        color,
+    );
+    draw_circle_lines(cx, cy, length / 2.0 + 6.0, 1.0, color);
+}
+
+// a scrolling bar chart of the recent frame times in the bottom left corner
+// the bars are scaled so the line at 60 fps is halfway up, and anything
+// slower than 30 fps is cut off at the top
+pub fn draw_fps_graph(fps: &crate::fps::FPS, color: Color) {
+    let (width, height) = (128.0, 50.0);
+    let (x, y) = (10.0, screen_height() - height - 10.0);
+    let max_ms = 1000.0 / 30.0;
+
+    let frames: Vec<f64> = fps.frame_ms().collect();
+    let bar_width = width / frames.len().max(1) as f32;
+    for (i, ms) in frames.iter().enumerate() {
+        let bar = height * (ms / max_ms).min(1.0) as f32;
+        let bar_color = if *ms > 1000.0 / 60.0 { RED } else { color };
+        draw_rectangle(x + i as f32 * bar_width, y + height - bar, bar_width, bar, bar_color);
+    }
+
+    draw_rectangle_lines(x, y, width, height, 1.0, color);
+    draw_line(x, y + height / 2.0, x + width, y + height / 2.0, 1.0, GRAY);
+    draw_text("16.6ms", x + width + 4.0, y + height / 2.0 + 4.0, 16.0, color);
+    draw_text("Frame times (G to hide)", x, y - 4.0, 16.0, color);
+}
+
+// a histogram of the total energy of every pendulum, along the bottom of the window
+// they all start with nearly the same energy, so the spread shows how
+// differently the solver's error has built up along each trajectory
+pub fn draw_energy_histogram(dp_vec: &[DoublePendulum], color: Color) {
+    let bins = 32;
+    let (x, width, height) = (180.0, screen_width() - 190.0, 50.0);
+    let y = screen_height() - height - 10.0;
+
+    let energies: Vec<f64> = dp_vec.iter().map(|dp| dp.total_energy()).collect();
+    let min = energies.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = energies.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    let spread = max - min;
+
+    let mut counts = vec![0; bins];
+    for energy in energies.iter() {
+        let bin = if spread > 0.0 { ((energy - min) / spread * bins as f64) as usize } else { bins / 2 };
+        counts[bin.min(bins - 1)] += 1;
+    }
+    let most = counts.iter().cloned().max().unwrap_or(0).max(1);
+
+    let bar_width = width / bins as f32;
+    for (i, count) in counts.iter().enumerate() {
+        let bar = height * *count as f32 / most as f32;
+        draw_rectangle(x + i as f32 * bar_width, y + height - bar, bar_width - 1.0, bar, color);
+    }
+    draw_rectangle_lines(x, y, width, height, 1.0, color);
+    draw_text(&format!("Energy from {:.6} to {:.6} (spread {:.2e}, H to hide)", min, max, spread), x, y - 4.0, 16.0, color);
+}
+
+// pendulums that have diverged are drawn in magenta, which is outside the
+// range of hues the fan uses, so it's clear which ones blew up
+fn shown_color(dp: &DoublePendulum, color: Color) -> Color {
+    if dp.diverged { Color { a: color.a, ..MAGENTA } } else { color }
+}
+
+// maps the speed of the outer bob from blue when still to red at the
+// speed it would reach falling from the highest point to the lowest
+fn speed_color(dp: &DoublePendulum, speed: f64, alpha: f32) -> Color {
+    let max_speed = (4.0 * dp.gravity * (dp.length1 + dp.length2)).sqrt();
+    let fraction = if max_speed > 0.0 { (speed / max_speed).min(1.0) } else { 1.0 };
+    let c = Rgb::from(Hsl::new(240.0 * (1.0 - fraction), 100.0, 50.0, None));
+    Color::new(c.red() as f32 / 255.0, c.green() as f32 / 255.0, c.blue() as f32 / 255.0, alpha)
+}
+
+// draws every pendulum as a point in phase space, at theta1 across and
+// angular1 up and down, with a trail of its previous points fading out behind it
+// theta1 is wrapped to [0, 2pi) so the plot stays in the window no matter
+// how many times the pendulum has gone over the top
+pub fn draw_phase(dp_vec: &[DoublePendulum], colors: &[Color]) {
+    let margin = 20.0;
+    let (width, height) = (screen_width() - 2.0 * margin, screen_height() - 2.0 * margin);
+
+    // scaled so that the fastest point, current or in a trail, just fits
+    let max_angular = dp_vec.iter()
+        .flat_map(|dp| dp.prev_angles.iter().map(|(_, _, angular1, _, _)| *angular1).chain(std::iter::once(dp.angular1)))
+        .fold(0.0_f64, |max, angular1| max.max(angular1.abs()));
+    let max_angular = if max_angular > 0.0 { max_angular } else { 1.0 };
+    let point = |theta1: f64, angular1: f64| {
+        let theta1 = theta1.rem_euclid(std::f64::consts::TAU);
+        (
+            margin + width * (theta1 / std::f64::consts::TAU) as f32,
+            margin + height * (0.5 - (angular1 / max_angular) as f32 / 2.0),
+        )
+    };
+
+    // the axes, theta1 = pi is the pendulum standing straight up
+    draw_line(margin, margin + height / 2.0, margin + width, margin + height / 2.0, 1.0, GRAY);
+    draw_line(margin + width / 2.0, margin, margin + width / 2.0, margin + height, 1.0, GRAY);
+
+    for (dp, color) in dp_vec.iter().zip(colors.iter()) {
+        let trail = dp.prev_angles.len().max(1) as f32;
+        for (i, (theta1, _, angular1, _, _)) in dp.prev_angles.iter().enumerate() {
+            let (x, y) = point(*theta1, *angular1);
+            let fade = Color::new(color.r, color.g, color.b, color.a * (1.0 - i as f32 / trail));
+            draw_rectangle(x - 1.0, y - 1.0, 2.0, 2.0, fade);
+        }
+
+        let (x, y) = point(dp.theta1, dp.angular1);
+        draw_circle(x, y, 3.0, Color::new(color.r, color.g, color.b, 1.0));
+    }
+}
+
+// a small phase portrait of one pendulum in the bottom right corner, above
+// the energy histogram, with theta2 across from -pi to pi and angular2 up
+// and down, so it can be watched alongside the pendulums
+// the trail comes from the trace, fading out towards its oldest point
+pub fn draw_phase_inset(dp: &DoublePendulum, color: Color, text_color: Color, background: Color) {
+    let (width, height) = (200.0, 150.0);
+    let (x, y) = (screen_width() - width - 10.0, screen_height() - height - 90.0);
+
+    let max_angular = dp.prev_angles.iter()
+        .map(|(_, _, _, angular2, _)| *angular2)
+        .chain(std::iter::once(dp.angular2))
+        .fold(0.0_f64, |max, angular2| max.max(angular2.abs()));
+    let max_angular = if max_angular > 0.0 { max_angular } else { 1.0 };
+    let point = |theta2: f64, angular2: f64| (
+        x + width * ((wrap_angle(theta2) / std::f64::consts::TAU) as f32 + 0.5),
+        y + height * (0.5 - (angular2 / max_angular) as f32 / 2.0),
+    );
+
+    draw_rectangle(x, y, width, height, Color { a: 0.8, ..background });
+    draw_line(x, y + height / 2.0, x + width, y + height / 2.0, 1.0, GRAY);
+    draw_line(x + width / 2.0, y, x + width / 2.0, y + height, 1.0, GRAY);
+    draw_rectangle_lines(x, y, width, height, 1.0, text_color);
+
+    let trail = dp.prev_angles.len().max(1) as f32;
+    for (i, (_, theta2, _, angular2, _)) in dp.prev_angles.iter().enumerate() {
+        let (px, py) = point(*theta2, *angular2);
+        draw_rectangle(px - 1.0, py - 1.0, 2.0, 2.0, Color { a: 1.0 - i as f32 / trail, ..color });
+    }
+    let (px, py) = point(dp.theta2, dp.angular2);
+    draw_circle(px, py, 3.0, Color { a: 1.0, ..color });
+    draw_text("Outer rod phase (J to hide)", x, y - 4.0, 16.0, text_color);
+    draw_text(&format!("{:.0} deg/s", max_angular.to_degrees()), x + 4.0, y + 14.0, 16.0, text_color);
+}
+
+// draws the poincare sections of every pendulum as a scatter plot filling the window
+// theta2 runs across from -pi to pi, and angular2 runs up and down,
+// scaled so that the fastest point just fits
+pub fn draw_poincare(dp_vec: &[DoublePendulum], colors: &[Color]) {
+    let margin = 20.0;
+    let (width, height) = (screen_width() - 2.0 * margin, screen_height() - 2.0 * margin);
+
+    let max_angular = dp_vec.iter()
+        .flat_map(|dp| dp.poincare.iter())
+        .fold(0.0_f64, |max, (_, angular2)| max.max(angular2.abs()));
+    let max_angular = if max_angular > 0.0 { max_angular } else { 1.0 };
+
+    // the axes
+    draw_line(margin, margin + height / 2.0, margin + width, margin + height / 2.0, 1.0, GRAY);
+    draw_line(margin + width / 2.0, margin, margin + width / 2.0, margin + height, 1.0, GRAY);
+
+    for (dp, color) in dp_vec.iter().zip(colors.iter()) {
+        for (theta2, angular2) in dp.poincare.iter() {
+            let x = margin + width * ((wrap_angle(*theta2) / std::f64::consts::TAU) as f32 + 0.5);
+            let y = margin + height * (0.5 - (angular2 / max_angular) as f32 / 2.0);
+            draw_rectangle(x - 1.0, y - 1.0, 2.0, 2.0, *color);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // a pendulum with a full trace of max_trace positions
+    fn traced(max_trace: usize) -> DoublePendulum {
+        let mut dp = DoublePendulum::new(1.0, 1.0, 1.0, 1.0, 2.0, 0.5).with_max_trace(max_trace);
+        while dp.prev_angles.len() < max_trace {
+            dp.update(1.0 / 240.0);
+        }
+        dp
+    }
+
+    fn style(decay: f32, stride: usize) -> Style {
+        Style { rod: 2.0, node: 8.0, trace: 4.0, decay, stride, fade: Fade::Off, pretty: false }
+    }
+
+    // the index, ends and thickness of every segment trace_segments gives
+    fn segments(dp: &DoublePendulum, style: &Style) -> Vec<(usize, TracePoint, TracePoint, f32)> {
+        let mut segments = Vec::new();
+        trace_segments(dp, style, |n, a, b, thickness| segments.push((n, a, b, thickness)));
+        segments
+    }
+
+    #[test]
+    fn the_tail_thins_the_same_however_long_it_is() {
+        for max_trace in [20, 150, 1000] {
+            let style = style(tail_decay(max_trace), 1);
+            let oldest = segments(&traced(max_trace), &style).last().unwrap().3;
+            assert!((oldest - style.trace * TAIL_THICKNESS).abs() < 1e-3, "{} positions end at {}", max_trace, oldest);
+        }
+    }
+
+    #[test]
+    fn a_stride_still_reaches_the_end_of_the_tail() {
+        let dp = traced(150);
+        let every = segments(&dp, &style(0.98, 1));
+        let strided = segments(&dp, &style(0.98, 4));
+        assert_eq!(every.len(), 149);
+        assert!(every.iter().all(|&(n, _, _, thickness)| (thickness - 4.0 * 0.98f32.powi(n as i32)).abs() < 1e-4));
+        assert_eq!(strided.len(), 149_usize.div_ceil(4));
+
+        // each strided segment starts where the unstrided one does, as thick
+        assert!(strided.iter().all(|&(n, a, _, thickness)| every[n].1 == a && every[n].3 == thickness));
+        assert_eq!(strided.last().unwrap().2, dp.prev_angles[149]);
+    }
+
+    #[test]
+    fn every_fade_but_off_ends_at_nothing() {
+        for fade in [Fade::Linear, Fade::Exponential] {
+            assert_eq!(fade.alpha(0.8, 0.0), 0.8);
+            assert!(fade.alpha(0.8, 1.0).abs() < 1e-6, "{:?} ends at {}", fade, fade.alpha(0.8, 1.0));
+        }
+        assert_eq!(Fade::Off.alpha(0.8, 1.0), 0.8);
+        assert!(Fade::Exponential.alpha(1.0, 0.25) < Fade::Linear.alpha(1.0, 0.25));
+    }
+}
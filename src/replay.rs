@@ -0,0 +1,86 @@
+// replay.rs
+
+// plays back a recorded trajectory instead of simulating it, so trajectories
+// from somewhere else, ex, a more precise solver, can be drawn the same way
+// the rows are read from the same csv format that E saves
+
+use crate::pendulum::wrap_angle;
+
+
+pub struct Replay {
+    // (t, theta1, theta2, angular1, angular2), in order of time
+    rows: Vec<(f64, f64, f64, f64, f64)>,
+}
+
+impl Replay {
+    // reads a trajectory csv, the header is skipped and every other
+    // non-empty line must have the five columns of a recorded row
+    pub fn load(path: &str) -> std::io::Result<Replay> {
+        let invalid = |line: usize, message: &str| {
+            std::io::Error::new(std::io::ErrorKind::InvalidData, format!("line {}: {}", line, message))
+        };
+
+        let mut rows = Vec::new();
+        for (i, line) in std::fs::read_to_string(path)?.lines().enumerate().skip(1) {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let values: Vec<f64> = line.split(',')
+                .map(|value| value.trim().parse::<f64>())
+                .collect::<Result<_, _>>()
+                .map_err(|e| invalid(i + 1, &e.to_string()))?;
+            if values.len() != 5 {
+                return Err(invalid(i + 1, &format!("expected 5 columns, found {}", values.len())));
+            }
+            rows.push((values[0], values[1], values[2], values[3], values[4]));
+        }
+
+        if rows.is_empty() {
+            return Err(invalid(1, "there are no rows"));
+        }
+        if rows.windows(2).any(|pair| pair[1].0 < pair[0].0) {
+            return Err(invalid(1, "the times are not in order"));
+        }
+        Ok(Replay { rows })
+    }
+
+    pub fn start(&self) -> f64 {
+        self.rows[0].0
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.rows[self.rows.len() - 1].0 - self.start()
+    }
+
+    // the time in the trajectory after playing it for elapsed seconds
+    // it loops back to the start once it reaches the end
+    pub fn time_at(&self, elapsed: f64) -> f64 {
+        if self.duration() > 0.0 {
+            self.start() + elapsed.rem_euclid(self.duration())
+        } else {
+            self.start()
+        }
+    }
+
+    // the state (theta1, theta2, angular1, angular2) at time t, linearly
+    // interpolated between the rows either side of it
+    // the angles are interpolated the short way round, so a row that
+    // wrapped to the other side of the circle doesn't sweep back across it
+    pub fn state_at(&self, t: f64) -> (f64, f64, f64, f64) {
+        // the first row after t, before the first or after the last the state is held
+        let next = self.rows.partition_point(|row| row.0 <= t);
+        let (a, b) = match next {
+            0 => (self.rows[0], self.rows[0]),
+            n if n == self.rows.len() => (self.rows[n - 1], self.rows[n - 1]),
+            n => (self.rows[n - 1], self.rows[n]),
+        };
+        let span = b.0 - a.0;
+        let fraction = if span > 0.0 { (t - a.0) / span } else { 0.0 };
+        (
+            a.1 + fraction * wrap_angle(b.1 - a.1),
+            a.2 + fraction * wrap_angle(b.2 - a.2),
+            a.3 + fraction * (b.3 - a.3),
+            a.4 + fraction * (b.4 - a.4),
+        )
+    }
+}